@@ -1,33 +1,83 @@
 #version 330 core
-layout (location = 0) in vec4 pos;
+
+# vert
+#ifdef INSTANCED
+// A unit quad once per instance, stretched, turned around its middle and moved to where the instance goes. Color is a byte per channel
+layout (location = 0) in vec2 corner;
+layout (location = 1) in vec2 i_pos;
+layout (location = 2) in vec2 i_size;
+layout (location = 3) in float i_rotation;
+layout (location = 4) in vec4 i_uv;
+layout (location = 5) in vec4 i_col;
+layout (location = 6) in int i_slot;
+#else
+layout (location = 0) in vec2 pos;
 layout (location = 1) in vec2 txt;
 layout (location = 2) in vec4 col;
-//uniform mat4 u_mvp;
+layout (location = 3) in int slot;
+#endif
+
+// Logical pixels from the top left to clip space
+uniform mat3 u_projection;
 
 out vec2 v_text;
 out vec4 v_col;
+flat out int v_slot;
 
 void main() {
-    gl_Position = pos;
-    // gl_Position = u_mvp * pos;
+#ifdef INSTANCED
+    // Only how far turning moved the corner gets added, so quads that aren't turned land exactly where the vertex path puts them
+    vec2 o = (corner - 0.5) * i_size;
+    float c = cos(i_rotation), s = sin(i_rotation);
+    vec2 pos = i_pos + corner * i_size + (vec2(o.x * c - o.y * s, o.x * s + o.y * c) - o);
+    vec2 txt = mix(i_uv.xy, i_uv.zw, corner);
+    vec4 col = i_col;
+    int slot = i_slot;
+#endif
+    gl_Position = vec4((u_projection * vec3(pos, 1.0)).xy, 0.0, 1.0);
     v_text = txt;
     v_col = col;
+    v_slot = slot;
 }
 
 # frag
-#version 330 core
 layout (location = 0) out vec4 color;
 
 in vec2 v_text;
 in vec4 v_col;
+flat in int v_slot;
 
-uniform sampler2D u_tex;
+// One per texture unit GL 3.3 has to have at least, the renderer binds up to this many textures per draw
+uniform sampler2D u_tex[16];
 
 vec4 s;
 
+// Sampler arrays can only be indexed by constants in 3.30, so every slot gets spelled out. Shapes have no texture (slot -1) and get white
+vec4 slot_texture(vec2 uv) {
+    switch (v_slot) {
+        case 0: return texture(u_tex[0], uv);
+        case 1: return texture(u_tex[1], uv);
+        case 2: return texture(u_tex[2], uv);
+        case 3: return texture(u_tex[3], uv);
+        case 4: return texture(u_tex[4], uv);
+        case 5: return texture(u_tex[5], uv);
+        case 6: return texture(u_tex[6], uv);
+        case 7: return texture(u_tex[7], uv);
+        case 8: return texture(u_tex[8], uv);
+        case 9: return texture(u_tex[9], uv);
+        case 10: return texture(u_tex[10], uv);
+        case 11: return texture(u_tex[11], uv);
+        case 12: return texture(u_tex[12], uv);
+        case 13: return texture(u_tex[13], uv);
+        case 14: return texture(u_tex[14], uv);
+        case 15: return texture(u_tex[15], uv);
+        default: return vec4(1.0);
+    }
+}
+
 void main() {
     // color = vec4(1.0, 0.0, 0.0, 1.0);
-    s = texture(u_tex, v_text);
+    s = slot_texture(v_text);
     color = vec4(mix(s.xyz, v_col.xyz, v_col.a), s.a);
     // color = vec4(textColor, 1.0) * sampled;
 }
@@ -1,7 +1,11 @@
 use std::ops;
+use crate::graphics::error::{RendererError, Result};
+use crate::graphics::batch::Blend;
+use crate::graphics::camera::Camera2D;
+use crate::graphics::handle::{TextureHandle, FontHandle};
 
 // ------- Vector Datatypes -------
-#[derive(Default, Copy, Clone, Debug)]
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
 #[repr(C)]
 pub struct Vec2<T> {
 	pub x: T,
@@ -17,13 +21,279 @@ impl_op_ex!(- |a: &Vec2<f32>, b: &Vec2<f32>| -> Vec2<f32> { Vec2::<f32> { x: a.x
 impl_op_ex!(* |a: &Vec2<f32>, b: &Vec2<f32>| -> Vec2<f32> { Vec2::<f32> { x: a.x * b.x, y: a.y * b.y } });
 impl_op_ex!(/ |a: &Vec2<f32>, b: &Vec2<f32>| -> Vec2<f32> { Vec2::<f32> { x: a.x / b.x, y: a.y / b.y } });
 
+// A 2D affine transform, as a 3x3 matrix whose bottom row is always 0 0 1. Points are columns, so a * b does b first
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+	pub m: [[f32; 3]; 3],
+}
+impl Default for Transform {
+	fn default() -> Self { Transform::IDENTITY }
+}
+impl Transform {
+	pub const IDENTITY: Transform = Transform { m: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] };
+
+	// a c e
+	// b d f
+	pub const fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Self {
+		Transform { m: [[a, c, e], [b, d, f], [0.0, 0.0, 1.0]] }
+	}
+	pub fn translation(x: f32, y: f32) -> Self { Transform::new(1.0, 0.0, 0.0, 1.0, x, y) }
+	pub fn scaling(x: f32, y: f32) -> Self { Transform::new(x, 0.0, 0.0, y, 0.0, 0.0) }
+	pub fn shearing(x: f32, y: f32) -> Self { Transform::new(1.0, y.tan(), x.tan(), 1.0, 0.0, 0.0) }
+
+	// In radians, and clockwise on screen since y goes down
+	pub fn rotation(angle: f32) -> Self {
+		let (s, c) = angle.sin_cos();
+		Transform::new(c, s, -s, c, 0.0, 0.0)
+	}
+
+	pub fn apply(&self, p: Vec2<f32>) -> Vec2<f32> {
+		let m = &self.m;
+		Vec2::new(m[0][0] * p.x + m[0][1] * p.y + m[0][2], m[1][0] * p.x + m[1][1] * p.y + m[1][2])
+	}
+
+	// Just the linear part, for directions and sizes
+	pub fn apply_vector(&self, v: Vec2<f32>) -> Vec2<f32> {
+		let m = &self.m;
+		Vec2::new(m[0][0] * v.x + m[0][1] * v.y, m[1][0] * v.x + m[1][1] * v.y)
+	}
+
+	pub fn determinant(&self) -> f32 { self.m[0][0] * self.m[1][1] - self.m[0][1] * self.m[1][0] }
+
+	// None when it squashes everything flat, so there's no undoing it
+	pub fn inverse(&self) -> Option<Transform> {
+		let det = self.determinant();
+		if det == 0.0 || !det.is_finite() { return None; }
+		let [[a, c, e], [b, d, f], _] = self.m;
+		let (a2, b2, c2, d2) = (d / det, -b / det, -c / det, a / det);
+		Some(Transform::new(a2, b2, c2, d2, -(a2 * e + c2 * f), -(b2 * e + d2 * f)))
+	}
+
+	// Column major, how a mat3 uniform takes it
+	pub fn columns(&self) -> [f32; 9] {
+		let m = &self.m;
+		[m[0][0], m[1][0], m[2][0], m[0][1], m[1][1], m[2][1], m[0][2], m[1][2], m[2][2]]
+	}
+}
+impl_op_ex!(* |a: &Transform, b: &Transform| -> Transform {
+	let mut m = [[0.0; 3]; 3];
+	for (i, row) in m.iter_mut().enumerate() {
+		for (j, v) in row.iter_mut().enumerate() { *v = (0..3).map(|k| a.m[i][k] * b.m[k][j]).sum(); }
+	}
+	Transform { m }
+});
+
+// Safe to call from game code, anything unsafe the backend needs stays inside it. Resources come back as handles, which
+// turn into a StaleHandle error instead of drawing garbage once what they point at has been freed
 pub trait GraphicsAPI {
-	unsafe fn setup(&mut self) -> &mut dyn GraphicsAPI;
-	unsafe fn draw(&mut self);
-	unsafe fn destroy(&mut self);
-	unsafe fn rect(&mut self, x: f32, y: f32, w: f32, h: f32);
-  unsafe fn load_image(&mut self, file: &str) -> Result<u32, image::ImageError>;
-  unsafe fn image(&mut self, image: u32, x: i32, y: i32, w: i32, h: i32);
-	unsafe fn load_font(&mut self) -> u32;
+	fn setup(&mut self) -> Result<&mut dyn GraphicsAPI>;
+	fn draw(&mut self) -> Result<()>;
+	fn destroy(&mut self) -> Result<()>;
+
+	// Positions and sizes are logical pixels, from the top left of the screen
+	fn rect(&mut self, x: f32, y: f32, w: f32, h: f32) -> Result<()>;
+
+	// Everything submitted after this goes on the layer, and lower layers are drawn first. Opaque things on the same layer can get
+	// drawn in any order so they batch better, translucent ones keep the order they came in. Goes back to 0 after every draw
+	fn layer(&mut self, layer: i32) -> Result<()>;
+
+	// What everything drawn after this looks like, which carries over from frame to frame. push_style saves it and pop_style goes
+	// back to the last one saved. Only rects (fill and stroke), images (tint) and GLContext::text (fill) read it, each with the blend
+	// mode too. There are no paths yet, and text on the other backends doesn't exist
+	fn style(&self) -> Style;
+	fn set_style(&mut self, style: Style) -> Result<()>;
+	fn push_style(&mut self) -> Result<()>;
+	fn pop_style(&mut self) -> Result<()>;
+	fn fill(&mut self, color: [f32; 4]) -> Result<()> { let s = self.style(); self.set_style(Style { fill: Some(color), ..s }) }
+	fn no_fill(&mut self) -> Result<()> { let s = self.style(); self.set_style(Style { fill: None, ..s }) }
+	fn stroke(&mut self, color: [f32; 4]) -> Result<()> { let s = self.style(); self.set_style(Style { stroke: Some(color), ..s }) }
+	fn no_stroke(&mut self) -> Result<()> { let s = self.style(); self.set_style(Style { stroke: None, ..s }) }
+	fn stroke_weight(&mut self, weight: f32) -> Result<()> { let s = self.style(); self.set_style(Style { stroke_weight: weight, ..s }) }
+	fn blend_mode(&mut self, mode: BlendMode) -> Result<()> { let s = self.style(); self.set_style(Style { blend: mode, ..s }) }
+	fn tint(&mut self, color: [f32; 4]) -> Result<()> { let s = self.style(); self.set_style(Style { tint: Some(color), ..s }) }
+	fn no_tint(&mut self) -> Result<()> { let s = self.style(); self.set_style(Style { tint: None, ..s }) }
+
+
+	// Where what's drawn after this goes, on top of where it'd go otherwise. Like layers it goes back to nothing after every draw. Each
+	// of these happens to what's drawn before the ones already set, so translate then rotate turns things in place and then moves them.
+	// Angles are radians, clockwise on screen
+	fn transform(&self) -> Transform;
+	fn set_transform(&mut self, transform: Transform) -> Result<()>;
+	fn push_matrix(&mut self) -> Result<()>;
+	fn pop_matrix(&mut self) -> Result<()>;
+	fn apply_transform(&mut self, transform: Transform) -> Result<()> { let t = self.transform(); self.set_transform(t * transform) }
+	fn reset_matrix(&mut self) -> Result<()> { self.set_transform(Transform::IDENTITY) }
+	fn translate(&mut self, x: f32, y: f32) -> Result<()> { self.apply_transform(Transform::translation(x, y)) }
+	fn rotate(&mut self, angle: f32) -> Result<()> { self.apply_transform(Transform::rotation(angle)) }
+	fn scale(&mut self, x: f32, y: f32) -> Result<()> { self.apply_transform(Transform::scaling(x, y)) }
+	fn shear(&mut self, x: f32, y: f32) -> Result<()> { self.apply_transform(Transform::shearing(x, y)) }
+
+	// What's being drawn to. Resize whenever the window changes size (physical pixels) or scale factor, the viewport and projection
+	// follow on the next draw
+	fn screen(&self) -> Screen;
+	fn set_screen(&mut self, screen: Screen) -> Result<()>;
+	fn resize(&mut self, w: u32, h: u32, scale: f32) -> Result<()> { let s = self.screen(); self.set_screen(Screen { size: Vec2::new(w, h), scale, ..s }) }
+	fn virtual_resolution(&mut self, w: u32, h: u32, scaling: Scaling) -> Result<()> {
+		let s = self.screen();
+		self.set_screen(Screen { virtual_size: Some(Vec2::new(w, h)), scaling, ..s })
+	}
+	fn no_virtual_resolution(&mut self) -> Result<()> { let s = self.screen(); self.set_screen(Screen { virtual_size: None, ..s }) }
+	fn letterbox(&mut self, color: [f32; 4]) -> Result<()> { let s = self.screen(); self.set_screen(Screen { letterbox: color, ..s }) }
+
+	// Draws what comes after in world units through a camera into its viewport, or straight onto the screen with none. Like the
+	// transform it goes back to none after every draw. Setting a few in one frame is how split screens (or a minimap) get drawn
+	fn camera(&self) -> Option<Camera2D>;
+	fn set_camera(&mut self, camera: Option<Camera2D>) -> Result<()>;
+
+	fn load_image(&mut self, file: &str) -> Result<TextureHandle>;
+	fn image(&mut self, image: TextureHandle, x: i32, y: i32, w: i32, h: i32) -> Result<()>;
+	fn free_texture(&mut self, texture: TextureHandle) -> Result<()>;
+	fn load_font(&mut self) -> Result<FontHandle>;
+	fn free_font(&mut self, font: FontHandle) -> Result<()>;
+}
+
+// How what's drawn gets combined with what's already there. Alpha is the usual, Add brightens (glows, flashes) and Multiply darkens (shadows)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode { #[default] Alpha, Add, Multiply }
+
+// Fill and stroke go on shapes, stroke weight is in the same units as the shape and half of it goes on either side of the edge. Tint goes
+// on images, and like every color its alpha is how much of it covers the texture rather than how see through it is
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Style {
+	pub fill: Option<[f32; 4]>,
+	pub stroke: Option<[f32; 4]>,
+	pub stroke_weight: f32,
+	pub blend: BlendMode,
+	pub tint: Option<[f32; 4]>,
 }
 
+// Red with no stroke, which is what rects always were
+impl Default for Style {
+	fn default() -> Self { Style { fill: Some([1.0, 0.0, 0.0, 1.0]), stroke: None, stroke_weight: 1.0, blend: BlendMode::Alpha, tint: None } }
+}
+
+impl Style {
+	// The rects (x, y, w, h) a rect turns into and their colors: the fill, then the stroke as four bars that don't overlap, so see
+	// through strokes don't get darker in the corners
+	pub fn rect(&self, x: f32, y: f32, w: f32, h: f32) -> Vec<([f32; 4], [f32; 4])> {
+		let mut ret = Vec::new();
+		if let Some(fill) = self.fill { ret.push(([x, y, w, h], fill)); }
+		if let Some(stroke) = self.stroke.filter(|_| self.stroke_weight > 0.0) {
+			let (sw, half) = (self.stroke_weight, self.stroke_weight * 0.5);
+			if h <= sw {
+				ret.push(([x - half, y - half, w + sw, h + sw], stroke));
+			} else {
+				ret.push(([x - half, y - half, w + sw, sw], stroke));
+				ret.push(([x - half, y + h - half, w + sw, sw], stroke));
+				ret.push(([x - half, y + half, sw, h - sw], stroke));
+				ret.push(([x + w - half, y + half, sw, h - sw], stroke));
+			}
+		}
+		ret
+	}
+
+	// Blending with anything but alpha depends on what's under it, so it has to keep its place like translucent things do
+	pub fn blend(&self, color: [f32; 4]) -> Blend {
+		if self.blend == BlendMode::Alpha { Blend::of(color) } else { Blend::Translucent }
+	}
+	pub fn image_blend(&self, translucent: bool) -> Blend {
+		if translucent || self.blend != BlendMode::Alpha { Blend::Translucent } else { Blend::Opaque }
+	}
+
+	// The vertex color images get, none of it without a tint
+	pub fn image_color(&self) -> [f32; 4] { self.tint.unwrap_or([1.0, 1.0, 1.0, 0.0]) }
+}
+
+// The current style (or transform) and the ones saved under it, for the backends to keep. `what` names the pop for the error
+#[derive(Default)]
+pub struct Stack<T> {
+	pub current: T,
+	saved: Vec<T>,
+}
+
+impl<T: Copy> Stack<T> {
+	pub fn push(&mut self) { self.saved.push(self.current); }
+	pub fn pop(&mut self, what: &'static str) -> Result<()> {
+		self.current = self.saved.pop().ok_or(RendererError::StackUnderflow(what))?;
+		Ok(())
+	}
+}
+
+// How a virtual resolution gets onto a screen that isn't its size. Fit scales it as big as it goes keeping its aspect, Integer does
+// the same in whole steps so pixel art stays crisp (and fits when the screen is too small for even 1x), and Stretch fills the screen
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scaling {
+	Fit,
+	Integer,
+	Stretch,
+}
+
+// What's being drawn to: its size in physical pixels, and how many of those make a logical pixel (winit's scale factor). Everything
+// drawn is in logical pixels from the top left, so a layout comes out the same size on a HiDPI display, just sharper. With a virtual
+// size those are virtual pixels instead, scaled onto the screen by `scaling`, with bars of `letterbox` around whatever it doesn't cover
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Screen {
+	pub size: Vec2<u32>,
+	pub scale: f32,
+	pub virtual_size: Option<Vec2<u32>>,
+	pub scaling: Scaling,
+	pub letterbox: [f32; 4],
+}
+
+impl Default for Screen {
+	fn default() -> Self { Screen::new(0, 0, 1.0) }
+}
+
+impl Screen {
+	pub fn new(w: u32, h: u32, scale: f32) -> Self {
+		Screen { size: Vec2::new(w, h), scale, virtual_size: None, scaling: Scaling::Fit, letterbox: [0.0, 0.0, 0.0, 1.0] }
+	}
+
+	pub fn logical_size(&self) -> Vec2<f32> {
+		match self.virtual_size {
+			Some(v) => Vec2::new(v.x as f32, v.y as f32),
+			None => Vec2::new(self.size.x as f32 / self.scale, self.size.y as f32 / self.scale),
+		}
+	}
+
+	// The physical pixels (x, y, w, h from the top left) the logical ones get drawn into, whole so edges land on pixels
+	pub fn viewport(&self) -> [i32; 4] {
+		let (w, h) = (self.size.x as i32, self.size.y as i32);
+		let Some(v) = self.virtual_size else { return [0, 0, w, h]; };
+		let fit = (w as f32 / v.x as f32).min(h as f32 / v.y as f32);
+		let k = match self.scaling {
+			Scaling::Stretch => return [0, 0, w, h],
+			Scaling::Fit => fit,
+			Scaling::Integer => if fit >= 1.0 { fit.floor() } else { fit },
+		};
+		let (vw, vh) = ((v.x as f32 * k).round() as i32, (v.y as f32 * k).round() as i32);
+		[(w - vw) / 2, (h - vh) / 2, vw, vh]
+	}
+
+	// The bits of the screen outside the viewport, which get the letterbox color
+	pub fn bars(&self) -> Vec<[i32; 4]> {
+		let (w, h) = (self.size.x as i32, self.size.y as i32);
+		let [x, y, vw, vh] = self.viewport();
+		[[0, 0, w, y], [0, y + vh, w, h - y - vh], [0, y, x, vh], [x + vw, y, w - x - vw, vh]].into_iter().filter(|b| b[2] > 0 && b[3] > 0).collect()
+	}
+
+	// Logical pixels to physical ones, and back, e.g. for the mouse
+	pub fn physical(&self) -> Transform {
+		let ([x, y, w, h], size) = (self.viewport(), self.logical_size());
+		Transform::new(w as f32 / size.x, 0.0, 0.0, h as f32 / size.y, x as f32, y as f32)
+	}
+	pub fn to_physical(&self, p: Vec2<f32>) -> Vec2<f32> { self.physical().apply(p) }
+	pub fn to_logical(&self, p: Vec2<f32>) -> Vec2<f32> { self.physical().inverse().unwrap_or_default().apply(p) }
+
+	// Logical pixels to clip space, which is what the vertex shader does with u_projection. y flips since clip space goes up. Clip space
+	// covers the viewport rather than the whole screen
+	pub fn projection(&self) -> Transform {
+		let size = self.logical_size();
+		Transform::new(2.0 / size.x, 0.0, 0.0, -2.0 / size.y, -1.0, 1.0)
+	}
+	pub fn to_clip(&self, p: Vec2<f32>) -> Vec2<f32> { self.projection().apply(p) }
+	pub fn from_clip(&self, p: Vec2<f32>) -> Vec2<f32> {
+		let size = self.logical_size();
+		Vec2::new((p.x + 1.0) * 0.5 * size.x, (1.0 - p.y) * 0.5 * size.y)
+	}
+}
@@ -0,0 +1,338 @@
+use crate::graphics::api::{BlendMode, Screen, Stack, Transform, Vec2};
+use crate::graphics::camera::Camera2D;
+use crate::graphics::handle::{Handle, MaterialHandle, TextureHandle};
+use vertex_derive::VertexLayout;
+
+// The vertex every backend batches up, matches the layout in res/shaders.glsl
+#[derive(Debug, Copy, Clone, VertexLayout)]
+#[repr(C)]
+pub struct ShapeData {
+	pub pos: Vec2<f32>,
+	pub tex: Vec2<f32>,
+	pub col: [f32; 4],
+
+	// Which of the textures bound for the draw it samples, NO_TEXTURE samples plain white
+	pub slot: i32,
+}
+impl ShapeData {
+	pub fn new(pos: Vec2<f32>) -> Self {
+
+		// oh my god rust doesn't even have basic ternary operators bruh im about to kms
+		ShapeData { pos, tex: Vec2 { x: if pos.x > 0.0 { 1.0 } else { 0.0 }, y: if pos.y > 0.0 {1.0} else {0.0} }, col: [1.0; 4], slot: NO_TEXTURE }
+	}
+}
+
+pub const NO_TEXTURE: i32 = -1;
+
+// Most vertices 16 bit indices can reach. Batches start over counting from a new base vertex before going past it
+pub const SEGMENT: usize = 65536;
+
+// A quad for the instanced path: its top left corner and size before turning, how far it's turned (radians, around its middle),
+// the texture coords at its (0, 0) and (1, 1) corners, and its color a byte per channel
+#[derive(Debug, Copy, Clone, VertexLayout)]
+#[repr(C)]
+pub struct Instance {
+	pub pos: Vec2<f32>,
+	pub size: Vec2<f32>,
+	pub rotation: f32,
+	pub uv: [f32; 4],
+	#[normalized]
+	pub col: [u8; 4],
+	pub slot: i32,
+}
+impl Instance {
+	pub fn new(pos: Vec2<f32>, size: Vec2<f32>, color: [f32; 4]) -> Self {
+		Instance { pos, size, rotation: 0.0, uv: [0.0, 0.0, 1.0, 1.0], col: pack(color), slot: NO_TEXTURE }
+	}
+
+	// The same 4 vertices the instanced vertex shader makes, for when it can't be used
+	pub fn vertices(&self) -> [ShapeData; 4] {
+		let (c, s) = (self.rotation.cos(), self.rotation.sin());
+		let col = unpack(self.col);
+		[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+			let corner = Vec2::new(x, y);
+			let o = (corner - Vec2::new(0.5, 0.5)) * self.size;
+			let turn = Vec2::new(o.x * c - o.y * s, o.x * s + o.y * c) - o;
+			let tex = Vec2::new(self.uv[0] + (self.uv[2] - self.uv[0]) * x, self.uv[1] + (self.uv[3] - self.uv[1]) * y);
+			ShapeData { pos: self.pos + corner * self.size + turn, tex, col, slot: self.slot }
+		})
+	}
+
+	// The same quad moved by a transform, if it's still something an instance can be: a rect that's been scaled, turned and moved
+	// (and only scaled evenly if it was already turned). Anything else, like shearing it, takes vertices
+	pub fn transformed(&self, t: &Transform) -> Option<Instance> {
+		if *t == Transform::IDENTITY { return Some(*self); }
+		let (x, y) = (t.apply_vector(Vec2::new(1.0, 0.0)), t.apply_vector(Vec2::new(0.0, 1.0)));
+		let (sx, sy) = (x.x.hypot(x.y), y.x.hypot(y.y));
+		let square = (x.x * y.x + x.y * y.y).abs() <= 1e-6 * sx * sy;
+		if t.determinant() <= 0.0 || !square || (self.rotation != 0.0 && (sx - sy).abs() > 1e-6 * sx) { return None; }
+		let size = self.size * Vec2::new(sx, sy);
+		let middle = t.apply(self.pos + self.size * Vec2::new(0.5, 0.5));
+		Some(Instance { pos: middle - size * Vec2::new(0.5, 0.5), size, rotation: self.rotation + x.y.atan2(x.x), ..*self })
+	}
+}
+
+// A byte per channel, which GL turns back into 0..1 floats
+pub fn pack(color: [f32; 4]) -> [u8; 4] {
+	color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+pub fn unpack(col: [u8; 4]) -> [f32; 4] {
+	col.map(|c| c as f32 / 255.0)
+}
+
+const TEXTUREH: f32 = 512.0;
+const TEXTUREW: f32 = 512.0;
+const TEXCOORDS: [Vec2<f32>; 4] = [Vec2::<f32>::new(1.0 - 2.5 / TEXTUREW, 1.0 - 2.5 / TEXTUREH),
+	Vec2::<f32>::new(1.0 - 2.5 / TEXTUREW, 1.0), Vec2::<f32>::new(1.0, 1.0 - 2.5 / TEXTUREH), Vec2::<f32>::new(1.0, 1.0)];
+
+// Whether what's drawn covers what's under it completely, or lets some of it through
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Blend { #[default] Opaque, Translucent }
+impl Blend {
+	pub fn of(color: [f32; 4]) -> Self { if color[3] < 1.0 { Blend::Translucent } else { Blend::Opaque } }
+}
+
+// What a submission gets sorted (and split into draw calls) by
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Key {
+
+	// Which of the frame's views it's drawn through, see Batch::set_camera
+	pub view: usize,
+	pub layer: i32,
+	pub blend: Blend,
+	pub mode: BlendMode,
+	pub material: Option<MaterialHandle>,
+
+	// Instances rather than vertices and indices
+	pub instanced: bool,
+	pub texture: Option<TextureHandle>,
+}
+
+// A run of indices (or instances) that went in together, how many uniform changes had been made by then (only GL has those), and
+// the first vertex of the segment its indices are in
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Submission {
+	pub key: Key,
+	pub epoch: usize,
+	pub base: usize,
+	pub start: usize,
+	pub end: usize,
+}
+
+// How a view gets onto the screen: the projection to its clip space (what u_projection gets), the physical pixels (x, y, w, h from
+// the top left) that covers, and straight to those pixels for backends that don't go through clip space
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct View {
+	pub projection: Transform,
+	pub viewport: [i32; 4],
+	pub physical: Transform,
+}
+impl View {
+	pub fn new(screen: &Screen, camera: Option<&Camera2D>) -> Self {
+		match camera {
+			Some(c) => View { projection: c.view_projection(screen), viewport: c.physical_viewport(screen), physical: screen.physical() * c.view(screen) },
+			None => View { projection: screen.projection(), viewport: screen.viewport(), physical: screen.physical() },
+		}
+	}
+}
+
+// Vertex and index data for a frame, shared by all of the batch renderers
+#[derive(Default)]
+pub struct Batch {
+	pub shapedata: Vec<ShapeData>,
+	pub indexdata: Vec<u32>,
+	pub instances: Vec<Instance>,
+	pub submissions: Vec<Submission>,
+
+	// Where the segment being pushed to starts, and whether some shape was too big to fit in one (so indices need 32 bits after all)
+	pub base: usize,
+	pub wide: bool,
+
+	// What everything pushed gets moved by on the way in, so it doesn't have to split draw calls
+	pub transforms: Stack<Transform>,
+
+	// The camera of every view set this frame (none is the whole screen) and the one being drawn through, view 0 is always no camera
+	pub cameras: Vec<Option<Camera2D>>,
+	pub view: usize,
+}
+impl Batch {
+	// Draws what comes after through a camera, or none. Always a new view, so a camera can be drawn through twice (a minimap over
+	// the scene it's from) and what's already batched keeps the view it had
+	pub fn set_camera(&mut self, camera: Option<Camera2D>) {
+		if self.cameras.is_empty() { self.cameras.push(None); }
+		self.cameras.push(camera);
+		self.view = self.cameras.len() - 1;
+	}
+	pub fn camera(&self) -> Option<Camera2D> { self.cameras.get(self.view).copied().flatten() }
+
+	// Puts the transform and camera back for the next frame, handing back the views what's batched gets drawn through
+	pub fn end_frame(&mut self, screen: &Screen) -> Vec<View> {
+		let mut views: Vec<View> = self.cameras.iter().map(|c| View::new(screen, c.as_ref())).collect();
+		if views.is_empty() { views.push(View::new(screen, None)); }
+		self.transforms = Stack::default();
+		self.cameras.clear();
+		self.view = 0;
+		views
+	}
+
+	// Marks everything from start up to what's been pushed so far as one submission, joining it onto the last one when nothing's different
+	pub fn submit(&mut self, key: Key, epoch: usize, start: usize) -> &mut Self {
+		let (end, base) = if key.instanced { (self.instances.len(), 0) } else { (self.indexdata.len(), self.base) };
+		match self.submissions.last_mut() {
+			Some(last) if last.key == key && last.epoch == epoch && last.base == base && last.end == start => last.end = end,
+			_ => self.submissions.push(Submission { key, epoch, base, start, end }),
+		}
+		self
+	}
+
+	// Puts the submissions (and their indices) in the order they get drawn: by view, then layer, then opaque before translucent, then by
+	// material and texture so as much as possible shares a draw call. Translucent things don't get grouped by material and texture,
+	// since what's under them has to be drawn first. The sort is stable, so anything else keeps the order it came in
+	pub fn sort(&mut self) {
+		fn id<T>(h: Option<Handle<T>>) -> Option<(u32, u32)> { h.map(|h| (h.index(), h.generation())) }
+		self.submissions.sort_by_key(|s| match s.key.blend {
+			Blend::Opaque => (s.key.view, s.key.layer, s.key.blend, id(s.key.material), s.key.instanced, id(s.key.texture)),
+			Blend::Translucent => (s.key.view, s.key.layer, s.key.blend, None, false, None),
+		});
+
+		let mut indices = Vec::with_capacity(self.indexdata.len());
+		let mut instances = Vec::with_capacity(self.instances.len());
+		for s in self.submissions.iter_mut() {
+			let start = if s.key.instanced { instances.len() } else { indices.len() };
+			if s.key.instanced { instances.extend_from_slice(&self.instances[s.start..s.end]); } else { indices.extend_from_slice(&self.indexdata[s.start..s.end]); }
+			(s.start, s.end) = (start, start + s.end - s.start);
+		}
+		self.indexdata = indices;
+		self.instances = instances;
+	}
+
+	// Starts a new segment if n more vertices won't fit in this one
+	fn segment(&mut self, n: usize) {
+		if self.shapedata.len() + n - self.base > SEGMENT { self.base = self.shapedata.len(); }
+		if n > SEGMENT { self.wide = true; }
+	}
+
+	pub fn push_shape(&mut self, points: Vec<Vec2<f32>>, index: Vec<u32>, color: [f32; 4]) -> &mut Self {
+		self.segment(points.len());
+
+		// Stores length of shapedata so we can add it to each of the indexes later
+		let len = self.shapedata.len();
+
+		// Adds every point into the shapedata buffer
+		let t = self.transforms.current;
+		for (i, pos) in points.into_iter().enumerate() {
+			self.shapedata.push(ShapeData {
+				col: color,
+				pos: t.apply(pos),
+				tex: TEXCOORDS[i % 4],
+				slot: NO_TEXTURE,
+			});
+		}
+
+		// Adds every index to the whole index buffer, and since we're appending the shapes, we're adding the length of the shape buffer so the indexes are referencing the proper shapes
+		for i in index {
+			self.indexdata.push(len as u32 + i); }
+
+		self
+	}
+
+	// Pushes a full vertex quad, for when the texture coords matter (images)
+	pub fn push_quad(&mut self, verts: [ShapeData; 4]) -> &mut Self {
+		self.segment(4);
+		let len = self.shapedata.len() as u32;
+		let t = self.transforms.current;
+		self.shapedata.extend(verts.map(|v| ShapeData { pos: t.apply(v.pos), ..v }));
+		self.indexdata.extend([0, 1, 2, 2, 1, 3].map(|i| len + i));
+		self
+	}
+
+	pub fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: [f32; 4]) -> &mut Self {
+		self.push_shape(Vec::<Vec2<f32>>::from([
+			Vec2::<f32> { x, y },
+			Vec2::<f32> { x: x + w, y },
+			Vec2::<f32> { x, y: y + h },
+			Vec2::<f32> { x: x + w, y: y + h },
+		]), vec![0, 1, 2, 2, 1, 3], color)
+	}
+
+	// Instances don't get transformed here, Instance::transformed says whether they can be
+	pub fn push_instance(&mut self, instance: Instance) -> &mut Self {
+		self.instances.push(instance);
+		self
+	}
+
+	pub fn is_empty(&self) -> bool { self.shapedata.is_empty() && self.instances.is_empty() }
+
+	pub fn clear(&mut self) {
+		self.shapedata.clear();
+		self.indexdata.clear();
+		self.instances.clear();
+		self.submissions.clear();
+		self.base = 0;
+		self.wide = false;
+	}
+}
+
+#[test]
+fn batch_sort() {
+	use crate::graphics::handle::{Pool, Texture};
+	let mut pool = Pool::<Texture, ()>::new();
+	let (a, b) = (pool.insert(()), pool.insert(()));
+	let mut batch = Batch::default();
+	let push = |batch: &mut Batch, layer: i32, blend: Blend, texture: Option<TextureHandle>| {
+		let start = batch.indexdata.len();
+		batch.rect(0.0, 0.0, 1.0, 1.0, [1.0; 4]);
+		batch.submit(Key { layer, blend, texture, ..Key::default() }, 0, start);
+		batch.indexdata[start] as usize
+	};
+
+	// Same key one after the other joins into one submission
+	let first = [push(&mut batch, 1, Blend::Opaque, Some(b)), push(&mut batch, 1, Blend::Opaque, Some(b))];
+	let glass = push(&mut batch, 0, Blend::Translucent, Some(b));
+	let glass2 = push(&mut batch, 0, Blend::Translucent, Some(a));
+	let under = push(&mut batch, 0, Blend::Opaque, Some(b));
+	let under2 = push(&mut batch, 0, Blend::Opaque, Some(a));
+	assert_eq!(batch.submissions.len(), 5);
+
+	// Layer first, then opaque by texture, then translucent in the order it came
+	batch.sort();
+	let order: Vec<usize> = batch.submissions.iter().map(|s| batch.indexdata[s.start] as usize).collect();
+	assert_eq!(order, [under2, under, glass, glass2, first[0]]);
+	assert_eq!(batch.indexdata[24..], [0, 1, 2, 2, 1, 3, 4, 5, 6, 6, 5, 7]);
+	assert!(batch.submissions.windows(2).all(|w| w[0].end == w[1].start));
+}
+
+#[test]
+fn batch_segments() {
+	let mut batch = Batch::default();
+	for _ in 0..20000 {
+		let start = batch.indexdata.len();
+		batch.rect(0.0, 0.0, 1.0, 1.0, [1.0; 4]);
+		batch.submit(Key::default(), 0, start);
+	}
+
+	// 16384 rects fill the first segment exactly, the rest start over from the vertex after
+	assert_eq!(batch.submissions.iter().map(|s| s.base).collect::<Vec<_>>(), [0, SEGMENT]);
+	assert!(batch.submissions.iter().all(|s| batch.indexdata[s.start..s.end].iter().all(|&i| (i as usize - s.base) < SEGMENT)));
+	assert!(!batch.wide);
+}
+
+#[test]
+fn batch_transforms() {
+	let close = |a: Vec2<f32>, b: Vec2<f32>| (a.x - b.x).abs() < 1e-5 && (a.y - b.y).abs() < 1e-5;
+	let t = Transform::translation(0.25, -0.5) * Transform::rotation(0.75) * Transform::scaling(2.0, 0.5);
+	assert!(close((t * t.inverse().unwrap()).apply(Vec2::new(3.0, 4.0)), Vec2::new(3.0, 4.0)));
+
+	// A transformed instance makes the same corners the vertex path would get from transforming the untransformed ones
+	let instance = Instance::new(Vec2::new(0.1, 0.2), Vec2::new(0.3, 0.4), [1.0; 4]);
+	let moved = instance.transformed(&t).unwrap();
+	assert!(moved.vertices().iter().zip(instance.vertices()).all(|(a, b)| close(a.pos, t.apply(b.pos))));
+
+	// Turned and then stretched across is a parallelogram, so is anything sheared
+	assert!(Instance { rotation: 0.5, ..instance }.transformed(&t).is_none());
+	assert!(instance.transformed(&Transform::shearing(0.3, 0.0)).is_none());
+	let mut batch = Batch::default();
+	batch.transforms.current = Transform::translation(1.0, 0.0);
+	batch.rect(0.0, 0.0, 1.0, 1.0, [1.0; 4]);
+	assert_eq!(batch.shapedata.iter().map(|v| v.pos.x).collect::<Vec<_>>(), [1.0, 2.0, 1.0, 2.0]);
+}
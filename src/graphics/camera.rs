@@ -0,0 +1,136 @@
+use crate::graphics::api::{Screen, Transform, Vec2};
+
+// A view onto a world bigger than the screen. `target` is the world point in the middle of the viewport, zoom is how many logical
+// pixels a world unit takes up and rotation turns the view (radians, clockwise, so the world turns the other way on screen). The
+// viewport is a logical rect (x, y, w, h) on the screen, all of it without one, and bounds is a world rect (x, y, w, h) the view
+// stays inside of, however close to its edge the target gets
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera2D {
+	pub target: Vec2<f32>,
+	pub zoom: f32,
+	pub rotation: f32,
+	pub viewport: Option<[f32; 4]>,
+	pub bounds: Option<[f32; 4]>,
+	pub shake: Shake,
+}
+
+// How hard the camera's shaking (in logical pixels, so zoom doesn't change it), for how long, and how long it's been going. Seconds
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Shake {
+	pub strength: f32,
+	pub duration: f32,
+	pub time: f32,
+}
+impl Shake {
+	// Dies down to nothing over the duration
+	pub fn current(&self) -> f32 {
+		if self.time >= self.duration { 0.0 } else { self.strength * (1.0 - self.time / self.duration) }
+	}
+}
+
+impl Default for Camera2D {
+	fn default() -> Self { Camera2D::new(Vec2::new(0.0, 0.0)) }
+}
+
+impl Camera2D {
+	pub fn new(target: Vec2<f32>) -> Self {
+		Camera2D { target, zoom: 1.0, rotation: 0.0, viewport: None, bounds: None, shake: Shake::default() }
+	}
+
+	// The logical rect on the screen it draws into
+	pub fn viewport_rect(&self, screen: &Screen) -> [f32; 4] {
+		let size = screen.logical_size();
+		self.viewport.unwrap_or([0.0, 0.0, size.x, size.y])
+	}
+
+	// Where it actually looks: the target, moved back inside the bounds by however much of the view would stick out of them. A view
+	// bigger than the bounds gets centered on them
+	pub fn clamped_target(&self, screen: &Screen) -> Vec2<f32> {
+		let Some([bx, by, bw, bh]) = self.bounds else { return self.target; };
+		let [_, _, w, h] = self.viewport_rect(screen);
+		let (s, c) = self.rotation.sin_cos();
+		let half = Vec2::new(w * c.abs() + h * s.abs(), w * s.abs() + h * c.abs()) * Vec2::new(0.5 / self.zoom, 0.5 / self.zoom);
+		let clamp = |t: f32, min: f32, size: f32, half: f32| if size <= half * 2.0 { min + size * 0.5 } else { t.clamp(min + half, min + size - half) };
+		Vec2::new(clamp(self.target.x, bx, bw, half.x), clamp(self.target.y, by, bh, half.y))
+	}
+
+	// Where the shake has the view this frame. It wobbles rather than jumping about randomly, so replays and tests come out the same
+	pub fn shake_offset(&self) -> Vec2<f32> {
+		let (k, time) = (self.shake.current(), self.shake.time);
+		Vec2::new((time * 47.0).sin() * k, (time * 61.0 + 1.3).sin() * k)
+	}
+
+	// World to logical screen pixels
+	pub fn view(&self, screen: &Screen) -> Transform {
+		let ([x, y, w, h], target, shake) = (self.viewport_rect(screen), self.clamped_target(screen), self.shake_offset());
+		Transform::translation(x + w * 0.5 + shake.x, y + h * 0.5 + shake.y) * Transform::rotation(-self.rotation) * Transform::scaling(self.zoom, self.zoom)
+			* Transform::translation(-target.x, -target.y)
+	}
+
+	// World to the clip space of its viewport, what the renderer puts in u_projection while drawing through it
+	pub fn view_projection(&self, screen: &Screen) -> Transform {
+		let [x, y, w, h] = self.viewport_rect(screen);
+		Transform::new(2.0 / w, 0.0, 0.0, -2.0 / h, -1.0, 1.0) * Transform::translation(-x, -y) * self.view(screen)
+	}
+
+	// The physical pixels (x, y, w, h from the top left) its viewport covers, whole like the screen's
+	pub fn physical_viewport(&self, screen: &Screen) -> [i32; 4] {
+		let [x, y, w, h] = self.viewport_rect(screen);
+		let (a, b) = (screen.to_physical(Vec2::new(x, y)), screen.to_physical(Vec2::new(x + w, y + h)));
+		let (x0, y0) = (a.x.round() as i32, a.y.round() as i32);
+		[x0, y0, b.x.round() as i32 - x0, b.y.round() as i32 - y0]
+	}
+
+	// Screen here is logical pixels, so the mouse wants Screen::to_logical first
+	pub fn world_to_screen(&self, p: Vec2<f32>, screen: &Screen) -> Vec2<f32> { self.view(screen).apply(p) }
+	pub fn screen_to_world(&self, p: Vec2<f32>, screen: &Screen) -> Vec2<f32> { self.view(screen).inverse().unwrap_or_default().apply(p) }
+
+	// Eases the target toward a point, covering the same share of the way each second whatever the frame rate. Higher speed keeps up closer
+	pub fn follow(&mut self, point: Vec2<f32>, speed: f32, dt: f32) {
+		let k = 1.0 - (-speed * dt).exp();
+		self.target = self.target + (point - self.target) * Vec2::new(k, k);
+	}
+
+	// Starts shaking, unless it's already shaking harder
+	pub fn shake(&mut self, strength: f32, duration: f32) {
+		if self.shake.current() <= strength { self.shake = Shake { strength, duration, time: 0.0 }; }
+	}
+
+	// Moves the shake along, call once a frame
+	pub fn update(&mut self, dt: f32) {
+		self.shake.time = (self.shake.time + dt).min(self.shake.duration);
+	}
+}
+
+#[test]
+fn camera_math() {
+	let screen = Screen::new(64, 32, 1.0);
+	let near = |a: Vec2<f32>, b: Vec2<f32>| (a - b).x.abs() < 1e-3 && (a - b).y.abs() < 1e-3;
+
+	// The target lands in the middle, zoom spreads the world out around it, and the mouse goes back the other way
+	let mut c = Camera2D { zoom: 2.0, ..Camera2D::new(Vec2::new(100.0, 50.0)) };
+	assert_eq!(c.world_to_screen(Vec2::new(110.0, 50.0), &screen), Vec2::new(52.0, 16.0));
+	assert_eq!(c.screen_to_world(Vec2::new(52.0, 16.0), &screen), Vec2::new(110.0, 50.0));
+	c.rotation = std::f32::consts::FRAC_PI_2;
+	assert!(near(c.world_to_screen(Vec2::new(110.0, 50.0), &screen), Vec2::new(32.0, -4.0)));
+
+	// Bounds keep the view inside them, or centered on them when they're smaller than it
+	let c = Camera2D { bounds: Some([0.0, 0.0, 100.0, 100.0]), ..Camera2D::default() };
+	assert_eq!(c.clamped_target(&screen), Vec2::new(32.0, 16.0));
+	assert_eq!(Camera2D { target: Vec2::new(1000.0, 50.0), ..c }.clamped_target(&screen), Vec2::new(68.0, 50.0));
+	assert_eq!(Camera2D { bounds: Some([0.0, 0.0, 10.0, 10.0]), ..c }.clamped_target(&screen), Vec2::new(5.0, 5.0));
+
+	// Following covers half the way in a second at ln 2
+	let mut c = Camera2D::default();
+	c.follow(Vec2::new(10.0, 0.0), std::f32::consts::LN_2, 1.0);
+	assert!(near(c.target, Vec2::new(5.0, 0.0)));
+
+	// Shaking dies down, and a weaker one doesn't cut a stronger one short
+	c.shake(4.0, 1.0);
+	assert_ne!(c.shake_offset(), Vec2::new(0.0, 0.0));
+	c.update(0.5);
+	c.shake(1.0, 1.0);
+	assert_eq!(c.shake.current(), 2.0);
+	c.update(1.0);
+	assert_eq!(c.shake_offset(), Vec2::new(0.0, 0.0));
+}
@@ -0,0 +1,110 @@
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShaderStage { Vertex, Geometry, Fragment }
+impl ShaderStage {
+	pub fn gl_type(self) -> u32 {
+		match self {
+			ShaderStage::Vertex => glow::VERTEX_SHADER,
+			ShaderStage::Geometry => glow::GEOMETRY_SHADER,
+			ShaderStage::Fragment => glow::FRAGMENT_SHADER,
+		}
+	}
+}
+
+// One message out of a driver's compile log, pointed back at the line of the file the stage came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderDiagnostic {
+	pub file: String,
+	pub line: usize,
+	pub col: Option<usize>,
+	pub message: String,
+
+	// The line it's about, quoted under the message
+	pub source: String,
+}
+
+// `file:line:col: message` and then the line with a caret under the column, like rustc and gcc do
+impl fmt::Display for ShaderDiagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.col {
+			Some(col) => write!(f, "{}:{}:{}: {}", self.file, self.line, col, self.message)?,
+			None => write!(f, "{}:{}: {}", self.file, self.line, self.message)?,
+		}
+		write!(f, "\n    | {}", self.source)?;
+		if let Some(col) = self.col {
+
+			// Keeps tabs as tabs so the caret lines up however wide they're shown
+			let pad: String = self.source.chars().take(col.saturating_sub(1)).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+			write!(f, "\n    | {}^", pad)?;
+		}
+		Ok(())
+	}
+}
+
+// Everything that can go wrong in the renderer, so the game gets to decide what to do about it instead of us panicking
+#[derive(Debug)]
+pub enum RendererError {
+	ShaderCompile { stage: ShaderStage, log: String, diagnostics: Vec<ShaderDiagnostic> },
+	ShaderSyntax(ShaderDiagnostic),
+	Link(String),
+	ResourceCreation(String),
+	ImageDecode(image::ImageError),
+	UnsupportedFormat(String),
+	FontParse(String),
+	AtlasFull(char),
+	StaleHandle(String),
+	UniformType { name: String, expected: &'static str, got: &'static str },
+	StackUnderflow(&'static str),
+	Io(std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, RendererError>;
+
+impl fmt::Display for RendererError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RendererError::ShaderCompile { stage, log, diagnostics } if diagnostics.is_empty() => write!(f, "{:?} shader failed to compile: {}", stage, log),
+			RendererError::ShaderCompile { stage, diagnostics, .. } => {
+				write!(f, "{:?} shader failed to compile:", stage)?;
+				for d in diagnostics { write!(f, "\n{}", d)?; }
+				Ok(())
+			}
+			RendererError::ShaderSyntax(d) => write!(f, "bad shader source: {}", d),
+			RendererError::Link(log) => write!(f, "shader program failed to link: {}", log),
+			RendererError::ResourceCreation(what) => write!(f, "couldn't create {}", what),
+			RendererError::ImageDecode(e) => write!(f, "couldn't load image: {}", e),
+			RendererError::UnsupportedFormat(format) => write!(f, "unsupported pixel format {}", format),
+			RendererError::FontParse(e) => write!(f, "couldn't parse font: {}", e),
+			RendererError::AtlasFull(c) => write!(f, "no room left in the font atlas for {:?}", c),
+			RendererError::StaleHandle(h) => write!(f, "{} was already freed", h),
+			RendererError::UniformType { name, expected, got } => write!(f, "uniform `{}` is a {} in the shader, not a {}", name, expected, got),
+			RendererError::StackUnderflow(what) => write!(f, "{} with nothing pushed to go back to", what),
+			RendererError::Io(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+impl std::error::Error for RendererError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			RendererError::ImageDecode(e) => Some(e),
+			RendererError::Io(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<std::io::Error> for RendererError {
+	fn from(e: std::io::Error) -> Self { RendererError::Io(e) }
+}
+
+// Failing to open the file is still just IO, the rest is the decoder's problem
+impl From<image::ImageError> for RendererError {
+	fn from(e: image::ImageError) -> Self {
+		match e {
+			image::ImageError::IoError(e) => RendererError::Io(e),
+			e => RendererError::ImageDecode(e),
+		}
+	}
+}
@@ -1,49 +1,112 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use glow::*;
-use crate::graphics::api::GraphicsAPI;
-use crate::graphics::api::Vec2;
+use crate::graphics::api::{BlendMode, GraphicsAPI, Screen, Stack, Style, Transform, Vec2};
+use crate::graphics::camera::Camera2D;
+use crate::graphics::batch::{Batch, Blend, Instance, Key, ShapeData, NO_TEXTURE, pack, unpack};
+use crate::graphics::tex::{Channels, FontAtlas, LoadedFont, Tex};
+use crate::graphics::error::{RendererError, Result, ShaderStage};
+use crate::graphics::shader::{Defines, ShaderSource, ShaderWatcher, defines};
+use crate::graphics::handle::{Pool, Texture, Font, Shader, Material, TextureHandle, FontHandle, ShaderHandle, MaterialHandle};
+use crate::graphics::uniform::{MaterialData, Program, UniformValue};
+use crate::graphics::stream::{StreamBuffer, StreamMode, as_bytes};
+use vertex_derive::VertexLayout;
+#[cfg(target_os = "linux")]
+use crate::graphics::headless::HeadlessGL;
 //use image::io;
 
-#[derive(Debug)]
-#[repr(C)]
-pub struct ShapeData {
-	pub pos: Vec2<f32>,
-	pub tex: Vec2<f32>,
-	pub col: [f32; 4]
-}
-impl ShapeData {
-	fn new(pos: Vec2<f32>) -> Self {
-
-		// oh my god rust doesn't even have basic ternary operators bruh im about to kms
-		ShapeData { pos, tex: Vec2 { x: if pos.x > 0.0 { 1.0 } else { 0.0 }, y: if pos.y > 0.0 {1.0} else {0.0} }, col: [1.0; 4] }
-	}
-}
-
-
 pub struct GLContext {
 	pub gl: glow::Context,
 	pub va: Option<glow::VertexArray>,
-	pub vb: Option<glow::Buffer>,
-	pub ib: Option<glow::Buffer>,
-	pub program: Option<glow::Program>,
 
-	pub texloc: Option<glow::UniformLocation>,
+	// Vertices and indices get streamed in fresh every frame, setup picks how. They're packed into the vertex format (and 16 bit
+	// indices counting from each segment's base, unless the batch is wide) on the way
+	pub vb: StreamBuffer,
+	pub ib: StreamBuffer,
+	pub format: VertexFormat,
+	packed_vertices: Vec<u8>,
+	packed_indices: Vec<u8>,
 
-	// For images :D
+	// Quads go through here instead when they can: a unit quad that never changes, and what to stretch it into once per instance.
+	// instanced is the program for that, None draws them as vertices like everything else
 	pub iva: Option<glow::VertexArray>,
-	pub ivb: Option<glow::Buffer>,
-	// pub iib: Option<glow::Buffer>,
+	unit_quad: Option<glow::Buffer>,
+	pub instances: StreamBuffer,
+	pub instanced: Option<ShaderHandle>,
+	pub program: Option<ShaderHandle>,
+
+	// Uniforms of the default program as they were last set, which carry over to the new program when the shaders reload. Changes
+	// wait in the queue, and every submission remembers how many were in it by then so draw can set them back to how they were for
+	// it, even after sorting put it after later ones
+	pub uniforms: HashMap<String, UniformValue>,
+	pub queue: Vec<Change>,
+	warned: HashSet<String>,
+
+	// The material shapes are being batched under, and the one whose program is bound while drawing. None is the default program, or the
+	// instanced one when quads is set
+	pub material: Option<MaterialHandle>,
+	active: Option<MaterialHandle>,
+	quads: bool,
+
+	// The layer shapes are being batched on, and the textures with see through texels (those get drawn in order with the rest of
+	// what's translucent)
+	pub layer: i32,
+	translucent: HashSet<TextureHandle>,
+
+	// How many textures get bound per draw call, running out of slots starts a new one
+	pub slots: usize,
+
+	// How many draw calls the last draw took
+	pub draw_calls: usize,
+	pub batch: Batch,
+
+	// Fill, stroke, blending and tint, and the ones push_style saved
+	pub styles: Stack<Style>,
+
+	// Everything the handles point at. The GL objects get deleted when their handle is freed, or on destroy
+	pub textures: Pool<Texture, glow::NativeTexture>,
+	pub fonts: Pool<Font, LoadedFont>,
+	pub shaders: Pool<Shader, Program>,
+	pub materials: Pool<Material, MaterialData>,
+
+	// The shader file setup loaded, and the programs built from it so far keyed by what was #defined. program is the one with nothing defined
+	pub source: Option<ShaderSource>,
+	pub variants: HashMap<Defines, ShaderHandle>,
 
-	pub uniforms: HashMap<String, i32>,
-	pub shapedata: Vec<ShapeData>,
-	prev_shp_size: usize,
-  pub indexdata: Vec<u32>,
-  prev_ind_size: usize,
-  pub curfill: [f32; 4],
+	// Debug builds reload the shaders when the file changes, and if the new ones don't build the error sits here while the old ones keep drawing
+	pub watcher: Option<ShaderWatcher>,
+	pub shader_error: Option<RendererError>,
 
-	pub textures: Vec<glow::NativeTexture>,
+	// What's drawn to, which u_projection maps logical pixels onto, and what bind puts in u_projection: the screen's or the camera's
+	pub screen: Screen,
+	projection: Transform,
 
-	pub window_size: glutin::dpi::PhysicalSize<u32>,
+	// Offscreen rendering, the framebuffer everything draws into when there's no window
+	pub fbo: Option<glow::Framebuffer>,
+	pub fbo_color: Option<glow::Renderbuffer>,
+	#[cfg(target_os = "linux")]
+	pub headless: Option<HeadlessGL>,
+}
+
+// Something set_uniform asked for, and what it replaced once draw made it so it can be undone
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+	pub material: Option<MaterialHandle>,
+	pub name: String,
+	pub value: UniformValue,
+	old: Option<UniformValue>,
+}
+
+// Sorted submissions that can go in one draw call, and the textures bound to units 0.. for it
+struct Call {
+	view: usize,
+	material: Option<MaterialHandle>,
+	quads: bool,
+	mode: BlendMode,
+	epoch: usize,
+	base: usize,
+	textures: Vec<TextureHandle>,
+	start: usize,
+	end: usize,
 }
 
 pub enum DrawPrimiv<'a> {
@@ -52,272 +115,961 @@ pub enum DrawPrimiv<'a> {
 }
 
 
+// Char is unsigned bytes, which the shader sees as 0..1 floats when they're normalized and as ints when they're not
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OpenGLType { Float, Integer, Char, Half }
+impl OpenGLType {
+	pub fn size(&self) -> i32 {
+		match self { OpenGLType::Float | OpenGLType::Integer => 4, OpenGLType::Half => 2, OpenGLType::Char => 1 }
+	}
+}
 #[derive(Debug)]
-pub enum OpenGLType { Float, Integer, Char }
-#[derive(Debug)]
-pub struct LayoutType { typeenum: OpenGLType, count: i32 }
+pub struct LayoutType { typeenum: OpenGLType, count: i32, offset: i32, normalized: bool }
 #[derive(Debug)]
 pub struct Layout {
 	types: Vec<LayoutType>,
 	pub stride: i32
 }
 
+impl Default for Layout {
+	fn default() -> Self { Self::new() }
+}
+
+// Something that goes to GL as vertices, usually from #[derive(VertexLayout)] so the layout can't drift from the struct
+pub trait VertexLayout {
+	fn layout() -> Layout;
+}
 
 impl Layout {
 	pub const fn new() -> Self {
 		Layout { types: Vec::<LayoutType>::new(), stride: 0 }
 	}
   pub fn addf(&mut self, count: i32) -> &mut Self {
-		self.types.push(LayoutType { count, typeenum: OpenGLType::Float });
-		self.stride += 4 * count;
-		self
+		self.add(OpenGLType::Float, count, self.stride, false)
 	}
   pub fn addi(&mut self, count: i32) -> &mut Self {
-		self.types.push(LayoutType { count, typeenum: OpenGLType::Integer });
-		self.stride += 4 * count;
-		self
+		self.add(OpenGLType::Integer, count, self.stride, false)
 	}
 
   pub fn addc(&mut self, count: i32) -> &mut Self {
-		self.types.push(LayoutType { count, typeenum: OpenGLType::Char });
-		self.stride += 1 * count;
+		self.add(OpenGLType::Char, count, self.stride, true)
+	}
+	pub fn addh(&mut self, count: i32) -> &mut Self {
+		self.add(OpenGLType::Half, count, self.stride, false)
+	}
+
+	// An attribute at a given offset, the stride grows to cover it but can be set bigger after for padding. Normalized only means
+	// anything for bytes
+	pub fn add(&mut self, typeenum: OpenGLType, count: i32, offset: i32, normalized: bool) -> &mut Self {
+		self.stride = self.stride.max(offset + typeenum.size() * count);
+		self.types.push(LayoutType { typeenum, count, offset, normalized: normalized && typeenum == OpenGLType::Char });
+		self
+	}
+
+	// Another layout's attributes after these, at the next locations. They're still read from their own buffer, so offsets stay as they were
+	pub fn append(&mut self, other: &Layout) -> &mut Self {
+		for t in other.types.iter() {
+			self.types.push(LayoutType { typeenum: t.typeenum, count: t.count, offset: t.offset, normalized: t.normalized });
+		}
 		self
 	}
 
-	pub unsafe fn apply(&mut self, gl: &glow::Context) {
-		let mut offset: i32 = 0;
-		for i in 0..self.types.len() {
-			let index: u32 = i.try_into().unwrap();
+	// What gets fed to the attribute at a location, and how many of it
+	pub fn attribute(&self, location: usize) -> Option<(&OpenGLType, i32)> {
+		self.types.get(location).map(|t| (&t.typeenum, t.count))
+	}
+	pub fn offset(&self, location: usize) -> Option<i32> { self.types.get(location).map(|t| t.offset) }
+	pub fn normalized(&self, location: usize) -> bool { self.types.get(location).is_some_and(|t| t.normalized) }
+	pub fn len(&self) -> usize { self.types.len() }
+	pub fn is_empty(&self) -> bool { self.types.is_empty() }
+
+	pub unsafe fn apply(&self, gl: &glow::Context) {
+		self.apply_at(gl, 0, 0, 0);
+	}
+
+	// Points the attributes at the bound buffer from location `first` on, with the data starting `offset` bytes in. A divisor of 1 steps
+	// them once per instance instead of per vertex
+	pub unsafe fn apply_at(&self, gl: &glow::Context, first: u32, offset: i32, divisor: u32) {
+		let stride = self.stride;
+		for (i, l) in self.types.iter().enumerate() {
+			let index = first + i as u32;
+			let offset = offset + l.offset;
 			gl.enable_vertex_attrib_array(index);
-			match &self.types[i] {
-				l @ LayoutType { typeenum: OpenGLType::Float, .. } => {
-					gl.vertex_attrib_pointer_f32(index, l.count, glow::FLOAT, false, self.stride, offset); offset += 4 * l.count; }
-				l @ LayoutType { typeenum: OpenGLType::Integer, .. } => {
-					gl.vertex_attrib_pointer_i32(index, l.count, glow::INT, self.stride, offset); offset += 4 * l.count; }
-        l @ LayoutType { typeenum: OpenGLType::Char, .. } => {
-          gl.vertex_attrib_pointer_i32(index, l.count, glow::UNSIGNED_BYTE, self.stride, offset); offset += 1 * l.count; }
+			gl.vertex_attrib_divisor(index, divisor);
+			match l.typeenum {
+				OpenGLType::Float => gl.vertex_attrib_pointer_f32(index, l.count, glow::FLOAT, false, stride, offset),
+				OpenGLType::Integer => gl.vertex_attrib_pointer_i32(index, l.count, glow::INT, stride, offset),
+				OpenGLType::Char if l.normalized => gl.vertex_attrib_pointer_f32(index, l.count, glow::UNSIGNED_BYTE, true, stride, offset),
+				OpenGLType::Char => gl.vertex_attrib_pointer_i32(index, l.count, glow::UNSIGNED_BYTE, stride, offset),
+				OpenGLType::Half => gl.vertex_attrib_pointer_f32(index, l.count, glow::HALF_FLOAT, false, stride, offset),
 			}
 		}
 	}
 }
 
-const TEXTUREH: f32 = 512.0;
-const TEXTUREW: f32 = 512.0;
-const TEXCOORDS: [Vec2<f32>; 4] = [Vec2::<f32>::new(1.0 - 2.5 / TEXTUREW, 1.0 - 2.5 / TEXTUREH),
-	Vec2::<f32>::new(1.0 - 2.5 / TEXTUREW, 1.0), Vec2::<f32>::new(1.0, 1.0 - 2.5 / TEXTUREH), Vec2::<f32>::new(1.0, 1.0)];
+// How ShapeData goes to the GPU. Packed colors are a normalized byte per channel instead of 4 floats, and half UVs take half the
+// room but only have 11 bits to place a texel with, so they're for small textures
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VertexFormat {
+	pub packed_colors: bool,
+	pub half_uvs: bool,
+}
+
+impl Default for VertexFormat {
+	fn default() -> Self { VertexFormat { packed_colors: true, half_uvs: false } }
+}
+
+// What the other formats look like in memory. Same order as ShapeData, so it's the same locations to the shader
+#[derive(Debug, Copy, Clone, VertexLayout)]
+#[repr(C)]
+pub struct PackedVertex {
+	pub pos: Vec2<f32>,
+	pub tex: Vec2<f32>,
+	#[normalized]
+	pub col: [u8; 4],
+	pub slot: i32,
+}
+#[derive(Debug, Copy, Clone, VertexLayout)]
+#[repr(C)]
+pub struct HalfVertex {
+	pub pos: Vec2<f32>,
+	pub tex: [half::f16; 2],
+	#[normalized]
+	pub col: [u8; 4],
+	pub slot: i32,
+}
+#[derive(Debug, Copy, Clone, VertexLayout)]
+#[repr(C)]
+pub struct HalfUvVertex {
+	pub pos: Vec2<f32>,
+	pub tex: [half::f16; 2],
+	pub col: [f32; 4],
+	pub slot: i32,
+}
+
+impl VertexFormat {
+	// pos, tex, col, slot. The shader sees the same types whichever it is
+	pub fn layout(&self) -> Layout {
+		match (self.packed_colors, self.half_uvs) {
+			(false, false) => ShapeData::layout(),
+			(true, false) => PackedVertex::layout(),
+			(true, true) => HalfVertex::layout(),
+			(false, true) => HalfUvVertex::layout(),
+		}
+	}
+
+	pub fn write(&self, data: &[ShapeData], out: &mut Vec<u8>) {
+		out.clear();
+		out.reserve(data.len() * self.layout().stride as usize);
+		let tex = |v: &ShapeData| [v.tex.x, v.tex.y].map(half::f16::from_f32);
+		match (self.packed_colors, self.half_uvs) {
+			(false, false) => out.extend_from_slice(as_bytes(data)),
+			(true, false) => convert(data, out, |v| PackedVertex { pos: v.pos, tex: v.tex, col: pack(v.col), slot: v.slot }),
+			(true, true) => convert(data, out, |v| HalfVertex { pos: v.pos, tex: tex(v), col: pack(v.col), slot: v.slot }),
+			(false, true) => convert(data, out, |v| HalfUvVertex { pos: v.pos, tex: tex(v), col: v.col, slot: v.slot }),
+		}
+	}
+}
+
+fn convert<T: Copy>(data: &[ShapeData], out: &mut Vec<u8>, f: impl Fn(&ShapeData) -> T) {
+	for v in data { out.extend_from_slice(as_bytes(&[f(v)])); }
+}
+
 impl GLContext {
+	// The window's context has to be current on this thread, and stay that way for as long as this is used
 	pub unsafe fn new(window: &glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>) -> Self {
+		let size = window.window().inner_size();
+		let screen = Screen::new(size.width, size.height, window.window().scale_factor() as f32);
+		GLContext::with_gl(glow::Context::from_loader_function(|s| window.get_proc_address(s) as *const _), screen)
+	}
+
+	pub fn with_gl(gl: glow::Context, screen: Screen) -> Self {
 		GLContext {
-			gl: glow::Context::from_loader_function(|s| window.get_proc_address(s) as *const _),
-			va: None, vb: None, ib: None, program: None, iva: None, ivb: None, texloc: None,
-			shapedata: Vec::<ShapeData>::new(),
-			indexdata: Vec::<u32>::new(),
-			uniforms: HashMap::<String, i32>::new(),
-      curfill: [1.0, 0.0, 0.0, 1.0],
-			prev_ind_size: 0, prev_shp_size: 0, textures: Vec::<glow::NativeTexture>::new(),
-			window_size: window.window().inner_size() }
+			gl,
+			va: None, program: None,
+			vb: StreamBuffer::new(glow::ARRAY_BUFFER, VertexFormat::default().layout().stride as usize, StreamMode::Orphan),
+			ib: StreamBuffer::new(glow::ELEMENT_ARRAY_BUFFER, core::mem::size_of::<u32>(), StreamMode::Orphan),
+			format: VertexFormat::default(), packed_vertices: Vec::new(), packed_indices: Vec::new(),
+			iva: None, unit_quad: None, instanced: None,
+			instances: StreamBuffer::new(glow::ARRAY_BUFFER, core::mem::size_of::<Instance>(), StreamMode::Orphan),
+			batch: Batch::default(),
+			uniforms: HashMap::new(), queue: Vec::new(), warned: HashSet::new(), material: None, active: None, quads: false, layer: 0,
+			translucent: HashSet::new(), slots: 1, draw_calls: 0,
+			styles: Stack::default(),
+			textures: Pool::new(), fonts: Pool::new(), shaders: Pool::new(), materials: Pool::new(),
+			source: None, variants: HashMap::new(), watcher: None, shader_error: None,
+			screen, projection: screen.projection(), fbo: None, fbo_color: None,
+			#[cfg(target_os = "linux")]
+			headless: None }
 	}
 
-	pub unsafe fn texture(&mut self, buf: Vec<u8>, width: i32, format: i32) -> u32 /*the id*/ {
-		let texture = self.gl.create_texture().ok();
-		self.gl.bind_texture(glow::TEXTURE_2D, texture);
+	// Makes a context with no window at all, rendering into a w by h framebuffer that frame() reads back
+	#[cfg(target_os = "linux")]
+	pub fn headless(w: u32, h: u32) -> Result<Self> {
+		unsafe {
+			let ctx = HeadlessGL::new(w, h).map_err(|e| RendererError::ResourceCreation(format!("headless context ({})", e)))?;
+			let mut g = GLContext::with_gl(glow::Context::from_loader_function(|s| ctx.get_proc_address(s)), Screen::new(w, h, 1.0));
+			g.headless = Some(ctx);
 
-		// Sets the default texture params, might add a way to change them later
-		self.gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
-		self.gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::LINEAR as i32);
-		self.gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
-		self.gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+			g.fbo = Some(g.gl.create_framebuffer().map_err(|e| RendererError::ResourceCreation(format!("framebuffer ({})", e)))?);
+			g.fbo_color = Some(g.gl.create_renderbuffer().map_err(|e| RendererError::ResourceCreation(format!("renderbuffer ({})", e)))?);
+			g.gl.bind_renderbuffer(glow::RENDERBUFFER, g.fbo_color);
+			g.gl.renderbuffer_storage(glow::RENDERBUFFER, glow::RGBA8, w as i32, h as i32);
+			g.gl.bind_framebuffer(glow::FRAMEBUFFER, g.fbo);
+			g.gl.framebuffer_renderbuffer(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::RENDERBUFFER, g.fbo_color);
+			if g.gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+				return Err(RendererError::ResourceCreation("offscreen framebuffer (it isn't complete)".into()));
+			}
 
-		self.gl.tex_image_2d(glow::TEXTURE_2D, 0, format, width, buf.len() as i32 / width, 0, format.try_into().unwrap(),
-			glow::UNSIGNED_BYTE, Some(core::slice::from_raw_parts(buf.as_ptr() as *const u8, buf.len() * core::mem::size_of::<u8>())));
+			// There's no surface to take the initial viewport from
+			g.gl.viewport(0, 0, w as i32, h as i32);
+			Ok(g)
+		}
+	}
+
+	// Reads back whatever was last drawn, top row first like every other Tex
+	pub fn frame(&self) -> Tex {
+		let (w, h) = (self.screen.size.x as usize, self.screen.size.y as usize);
+		let mut tex = Tex::new(w, h, Channels::RGBA);
+		unsafe {
+			self.gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+			self.gl.read_pixels(0, 0, w as i32, h as i32, glow::RGBA, glow::UNSIGNED_BYTE, glow::PixelPackData::Slice(&mut tex.data));
+		}
 
-		self.textures.push(texture.unwrap());
-		(self.textures.len() - 1) as u32
+		// GL hands rows back bottom up
+		let row = w * 4;
+		for y in 0..h / 2 {
+			let (top, bottom) = tex.data.split_at_mut((h - 1 - y) * row);
+			top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
+		}
+		tex
 	}
 
-	pub unsafe fn set_texture(&mut self, tex: i32) {
-		// why is rust so painnnnnnnnnnnnnn like wth is it even making me do :cry:
-		if self.texloc.is_none() { self.texloc = self.gl.get_uniform_location(self.program.unwrap(), "u_tex"); }
-		self.gl.uniform_1_i32((&self.texloc).as_ref(), tex);
+	// Clears to nothing with the letterbox color in the bars, then points the viewport at what's left. GL counts rows from the bottom
+	unsafe fn clear(&self) {
+		let h = self.screen.size.y as i32;
+		self.gl.clear(glow::COLOR_BUFFER_BIT);
+		let bars = self.screen.bars();
+		if !bars.is_empty() {
+			let c = self.screen.letterbox;
+			self.gl.clear_color(c[0], c[1], c[2], c[3]);
+			self.gl.enable(glow::SCISSOR_TEST);
+			for [x, y, w, bh] in bars {
+				self.gl.scissor(x, h - y - bh, w, bh);
+				self.gl.clear(glow::COLOR_BUFFER_BIT);
+			}
+			self.gl.disable(glow::SCISSOR_TEST);
+			self.gl.clear_color(0.0, 0.0, 0.0, 0.0);
+		}
+		let [x, y, w, vh] = self.screen.viewport();
+		self.gl.viewport(x, h - y - vh, w, vh);
 	}
 
-	pub fn push_shape(&mut self, points: Vec<Vec2<f32>>, index: Vec<u32>, color: [f32; 4]) -> &mut Self {
+	pub fn texture(&mut self, tex: &Tex) -> Result<TextureHandle> {
+		unsafe {
+			let texture = self.gl.create_texture().map_err(|e| RendererError::ResourceCreation(format!("texture ({})", e)))?;
+			self.gl.bind_texture(glow::TEXTURE_2D, Some(texture));
 
-		// Stores length of shapedata so we can add it to each of the indexes later
-		let len = self.shapedata.len();
+			// Sets the default texture params, might add a way to change them later
+			self.gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
+			self.gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::LINEAR as i32);
+			self.gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+			self.gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
 
-		// Adds every point into the shapedata buffer
-		for i in 0..points.len() {
-			self.shapedata.push(ShapeData {
-				col: color,
-				pos: points[i],
-				tex: TEXCOORDS[i % 4]
-			});
+			// Grayscale goes in the red channel, and rows of RGB/grayscale data aren't 4 byte aligned
+			let format = match tex.channels { Channels::RGB => glow::RGB, Channels::RGBA => glow::RGBA, Channels::GRAYSCALE => glow::RED };
+			self.gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+			self.gl.tex_image_2d(glow::TEXTURE_2D, 0, format as i32, tex.w as i32, tex.h as i32, 0, format,
+				glow::UNSIGNED_BYTE, Some(&tex.data));
+
+			let handle = self.textures.insert(texture);
+			if !tex.opaque() { self.translucent.insert(handle); }
+			Ok(handle)
 		}
+	}
 
-		// Adds every index to the whole index buffer, and since we're appending the shapes, we're adding the length of the shape buffer so the indexes are referencing the proper shapes
-		for i in 0..index.len() {
-			self.indexdata.push(len as u32 + index[i]); }
+	// Sets a uniform of the default program for everything batched after this, checking it against what the program says it is.
+	// Names the program doesn't have get warned about the first time and otherwise ignored, since the compiler strips out uniforms
+	// nothing ends up using
+	pub fn set_uniform(&mut self, name: &str, value: impl Into<UniformValue>) -> Result<()> {
+		self.queue_uniform(None, name, value.into())
+	}
 
-		self
+	// Same but for a material's program, only touching what that material draws
+	pub fn set_material_uniform(&mut self, material: MaterialHandle, name: &str, value: impl Into<UniformValue>) -> Result<()> {
+		self.queue_uniform(Some(material), name, value.into())
+	}
+
+	fn queue_uniform(&mut self, material: Option<MaterialHandle>, name: &str, value: UniformValue) -> Result<()> {
+		if self.target(material)?.reflection.check(name, &value)?.is_none() {
+			if self.warned.insert(name.to_string()) { eprintln!("No uniform called `{}` in the shader, ignoring it", name); }
+			return Ok(());
+		}
+		self.queue.push(Change { material, name: name.to_string(), value, old: None });
+		Ok(())
+	}
+
+	// The program a material draws with, or the default one
+	fn target(&self, material: Option<MaterialHandle>) -> Result<&Program> {
+		let shader = match material {
+			Some(m) => self.materials.get(m)?.shader,
+			None => self.program.ok_or_else(|| RendererError::ResourceCreation("uniform (no shader program yet)".into()))?,
+		};
+		self.shaders.get(shader)
+	}
+
+	// Makes a material out of a program that takes ShapeData like the default one does
+	pub fn material(&mut self, shader: ShaderHandle) -> Result<MaterialHandle> {
+		let layout = self.vertex_layout();
+		if let Some((name, a)) = self.shaders.get(shader)?.reflection.attributes.iter().find(|(_, a)| layout.attribute(a.location as usize).is_none()) {
+			return Err(RendererError::ResourceCreation(format!("material (`{}` is at location {}, past the end of the vertex layout)", name, a.location)));
+		}
+		// Its samplers start out pointed at the slots too, same as the default program's
+		let uniforms = (0..self.slots).map(|slot| (format!("u_tex[{}]", slot), UniformValue::Sampler(slot as i32)))
+			.filter(|(name, value)| matches!(self.shaders.get(shader).map(|p| p.reflection.check(name, value)), Ok(Ok(Some(_))))).collect();
+		Ok(self.materials.insert(MaterialData { shader, uniforms }))
+	}
+
+	pub fn free_material(&mut self, material: MaterialHandle) -> Result<()> {
+		self.materials.remove(material)?;
+		if self.material == Some(material) { self.material = None; }
+		Ok(())
+	}
+
+	// Everything batched after this gets drawn with the material, or the default program for None. Goes back to the default after every draw
+	pub fn set_material(&mut self, material: Option<MaterialHandle>) -> Result<()> {
+		if let Some(m) = material { self.materials.get(m)?; }
+		self.material = material;
+		Ok(())
+	}
+
+	// Sets a uniform right away, on the GL side too if it's for the bound program, and hands back what it was before
+	fn apply(&mut self, material: Option<MaterialHandle>, name: &str, value: UniformValue) -> Result<Option<UniformValue>> {
+		if material == self.active {
+			if let Some(info) = self.bound()?.reflection.check(name, &value)? {
+				unsafe { value.apply(&self.gl, &info.location); }
+			}
+		}
+		Ok(match material {
+			Some(m) => self.materials.get_mut(m)?.uniforms.insert(name.to_string(), value),
+			None => self.uniforms.insert(name.to_string(), value),
+		})
+	}
+
+	// Makes (or undoes) changes until the first `epoch` of them are made. Ones that were never set before go back to zero, like GL starts them
+	fn seek(&mut self, changes: &mut [Change], at: &mut usize, epoch: usize) -> Result<()> {
+		while *at < epoch {
+			let c = &mut changes[*at];
+			c.old = self.apply(c.material, &c.name, c.value)?;
+			*at += 1;
+		}
+		while *at > epoch {
+			*at -= 1;
+			let c = &changes[*at];
+			self.apply(c.material, &c.name, c.old.unwrap_or_else(|| c.value.zeroed()))?;
+		}
+		Ok(())
+	}
+
+	// Marks what's been batched since start as one submission, under the layer and material it was batched with
+	fn submit(&mut self, start: usize, blend: Blend, texture: Option<TextureHandle>, instanced: bool) {
+		let key = Key { view: self.batch.view, layer: self.layer, blend, mode: self.styles.current.blend, material: self.material, instanced, texture };
+		self.batch.submit(key, self.queue.len(), start);
+	}
+
+	// Binds a material's program (or the default, or the instanced one which gets the default's uniforms) and puts its uniform values in,
+	// since materials sharing a program each have their own. Every program gets u_projection for the view being drawn through
+	fn bind(&mut self, material: Option<MaterialHandle>, quads: bool) -> Result<()> {
+		self.active = material;
+		self.quads = quads && material.is_none();
+		unsafe { self.gl.bind_vertex_array(if self.quads { self.iva } else { self.va }); }
+		if material.is_none() && self.program.is_none() { return Ok(()); }
+		let program = self.bound()?;
+		let uniforms = match material { Some(m) => &self.materials.get(m)?.uniforms, None => &self.uniforms };
+		let projection = UniformValue::Mat3(self.projection.columns());
+		unsafe {
+			self.gl.use_program(Some(program.native));
+			for (name, value) in uniforms {
+				if let Ok(Some(info)) = program.reflection.check(name, value) { value.apply(&self.gl, &info.location); }
+			}
+			if let Ok(Some(info)) = program.reflection.check("u_projection", &projection) { projection.apply(&self.gl, &info.location); }
+		}
+		Ok(())
 	}
 
-	pub fn convert_screencoords(&self, arr: Vec<Vec2<i32>>) -> Vec<Vec2<f32>> {
-		let mut ret = Vec::<Vec2<f32>>::with_capacity(arr.len());
-		let w = self.window_size.width as i32; let wf = w as f32;
-		let h = self.window_size.height as i32; let hf = h as f32;
-		for i in arr {
-			ret.push(Vec2 { x: (i.x - w / 2) as f32 / wf, y: (i.y - h / 2) as f32 / hf });
+	// The program bind last put in
+	fn bound(&self) -> Result<&Program> {
+		match self.instanced {
+			Some(instanced) if self.quads => self.shaders.get(instanced),
+			_ => self.target(self.active),
 		}
+	}
+
+	// Draws indices start..end of what's uploaded, which is `base` vertices and `offset` bytes of indices into the buffers
+	fn draw_elements(&mut self, start: usize, end: usize, base: i32, offset: usize) {
+		if end <= start { return; }
+		self.draw_calls += 1;
+		let (kind, size) = if self.batch.wide { (glow::UNSIGNED_INT, 4) } else { (glow::UNSIGNED_SHORT, 2) };
+		unsafe { self.gl.draw_elements_base_vertex(glow::TRIANGLES, (end - start) as i32, kind, (offset + start * size) as i32, base); }
+	}
+
+	// Indices of the sorted batch counting from their segment's base, 16 bits each unless something didn't fit in a segment
+	fn pack_indices(&mut self) {
+		let Batch { indexdata, submissions, wide, .. } = &self.batch;
+		let out = &mut self.packed_indices;
+		out.clear();
+		for s in submissions.iter().filter(|s| !s.key.instanced) {
+			for &i in &indexdata[s.start..s.end] {
+				let i = i - s.base as u32;
+				if *wide { out.extend(i.to_ne_bytes()); } else { out.extend((i as u16).to_ne_bytes()); }
+			}
+		}
+	}
+
+	// Draws instances start..end of what's uploaded, `offset` bytes into the instance buffer
+	fn draw_instances(&mut self, start: usize, end: usize, offset: usize) {
+		if end <= start { return; }
+		self.draw_calls += 1;
+		let offset = (offset + start * core::mem::size_of::<Instance>()) as i32;
+		unsafe {
+			self.gl.bind_buffer(glow::ARRAY_BUFFER, self.instances.buffer);
+			Instance::layout().apply_at(&self.gl, 1, offset, 1);
+			self.gl.draw_arrays_instanced(glow::TRIANGLE_STRIP, 0, 4, (end - start) as i32);
+		}
+	}
+
+	pub fn push_shape(&mut self, points: Vec<Vec2<f32>>, index: Vec<u32>, color: [f32; 4]) -> &mut Self {
+		let start = self.batch.indexdata.len();
+		self.batch.push_shape(points, index, color);
+		self.submit(start, self.styles.current.blend(color), None, false);
+		self
+	}
+
+	// Compiles and links a vertex + fragment shader pair, handing back a handle to the program
+	pub fn shader(&mut self, path: &str, text: &str) -> Result<ShaderHandle> {
+		let program = self.load_shaders(path, text)?;
+		Ok(self.shaders.insert(unsafe { Program::new(&self.gl, program) }))
+	}
+
+	pub fn free_shader(&mut self, shader: ShaderHandle) -> Result<()> {
+		let program = self.shaders.remove(shader)?;
+		if self.program == Some(shader) { self.program = None; }
+		if self.instanced == Some(shader) { self.instanced = None; }
+		self.variants.retain(|_, v| *v != shader);
+		unsafe { self.gl.delete_program(program.native); }
+		Ok(())
+	}
+
+	// How ShapeData is laid out for the vertex shader
+	pub fn vertex_layout(&self) -> Layout { self.format.layout() }
+
+	// Switches what vertices get packed into, which takes a new vertex buffer since offsets have to land on a whole vertex
+	pub fn set_vertex_format(&mut self, format: VertexFormat) {
+		self.format = format;
+		self.vb.destroy(&self.gl);
+		self.vb = StreamBuffer::new(glow::ARRAY_BUFFER, format.layout().stride as usize, self.vb.mode);
+	}
+
+	// What the INSTANCED vertex shader takes: the unit quad's corner, then pos, size, rotation, uv, col and slot of an Instance
+	pub fn instance_layout() -> Layout {
+		let mut ret = Layout::new();
+		ret.addf(2).append(&Instance::layout());
 		ret
 	}
 
-	pub unsafe fn load_shaders(&self, file: &str) -> glow::Program {
+	// Instanced drawing is core from 3.3, before that it takes both extensions
+	pub fn can_instance(gl: &glow::Context) -> bool {
+		let v = gl.version();
+		let ext = gl.supported_extensions();
+		(v.major, v.minor) >= (3, 3) || (ext.contains("GL_ARB_instanced_arrays") && ext.contains("GL_ARB_draw_instanced"))
+	}
 
-		// Splits the file up by the string "# frag"
-		let (vert, frag): (&str, &str) = {
-			let mut split = file.split("# frag");
-			(split.next().unwrap(), split.next().unwrap())
+	// Batches a quad, as an instance if there's an instanced program, no material (materials take vertices) and the transform leaves
+	// it a rect
+	pub fn quad(&mut self, instance: Instance, texture: Option<TextureHandle>) -> Result<()> {
+		if let Some(t) = texture { self.textures.get(t)?; }
+		let blend = match texture {
+			Some(t) => self.styles.current.image_blend(self.translucent.contains(&t)),
+			None => self.styles.current.blend(unpack(instance.col)),
 		};
+		let transformed = instance.transformed(&self.batch.transforms.current).filter(|_| self.instanced.is_some() && self.material.is_none());
+		if let Some(instance) = transformed {
+			let start = self.batch.instances.len();
+			self.batch.push_instance(instance);
+			self.submit(start, blend, texture, true);
+		} else {
+			let start = self.batch.indexdata.len();
+			self.batch.push_quad(instance.vertices());
+			self.submit(start, blend, texture, false);
+		}
+		Ok(())
+	}
 
-		// Creates a new program so we can return
-		let program = self.gl.create_program().expect("bruh i can't even create a program wtf is this");
+	// Batches a quad sampling a texture, draw fills in the slot in the vertices once it knows which textures go together
+	pub fn textured_quad(&mut self, texture: TextureHandle, verts: [ShapeData; 4]) -> Result<()> {
+		self.textures.get(texture)?;
+		let start = self.batch.indexdata.len();
+		self.batch.push_quad(verts);
+		self.submit(start, self.styles.current.image_blend(self.translucent.contains(&texture)), Some(texture), false);
+		Ok(())
+	}
 
-		// Compiles shaders
-		let compile = |t: u32, s: &str| -> glow::Shader {
-			let shader = self.gl.create_shader(t).expect("bruh can't even create shaders bad");
-			self.gl.shader_source(shader, s);
-			self.gl.compile_shader(shader);
-			if !self.gl.get_shader_compile_status(shader) {
-				panic!("{}", self.gl.get_shader_info_log(shader));
+	// Lays a line of text out from its top left corner, `size` pixels tall, in the style's fill. Each glyph is a quad out of the font's
+	// atlas going through the same slots as everything else, so text in between rects and images doesn't take calls of its own.
+	// Characters the atlas doesn't have are skipped
+	pub fn text(&mut self, font: FontHandle, text: &str, x: f32, y: f32, size: f32) -> Result<()> {
+		let Some(fill) = self.styles.current.fill else { return Ok(()); };
+		let LoadedFont { atlas, texture } = self.fonts.get(font)?;
+		let (k, texture, atlas_size) = (size / FontAtlas::SIZE, *texture, Vec2::new(atlas.tex.w as f32, atlas.tex.h as f32));
+		let baseline = y + atlas.ascent("default") * k;
+		let mut pen = x;
+		let mut quads = Vec::new();
+		for c in text.chars() {
+			let Some(g) = atlas.glyph(c) else { continue; };
+			let (pos, glyph) = (Vec2::new(g.pos.x as f32, g.pos.y as f32), Vec2::new(g.size.x as f32, g.size.y as f32));
+			if g.size.x > 0 && g.size.y > 0 {
+				let at = Vec2::new(pen + g.offset.x as f32 * k, baseline - (g.offset.y as f32 + glyph.y) * k);
+				let (a, b) = (pos / atlas_size, (pos + glyph) / atlas_size);
+				quads.push(Instance { uv: [a.x, a.y, b.x, b.y], ..Instance::new(at, glyph * Vec2::new(k, k), fill) });
 			}
-			self.gl.attach_shader(program, shader);
+			pen += g.advance_x as f32 * k;
+		}
+		for quad in quads { self.quad(quad, Some(texture))?; }
+		Ok(())
+	}
 
-			shader
-		};
+	// Groups the sorted submissions into draw calls, splitting wherever the material, blend mode or uniforms change or the slots run out, and
+	// points each vertex at the slot its texture got
+	fn calls(&mut self) -> Vec<Call> {
+		let slots = self.slots;
+		let Batch { shapedata, indexdata, instances, submissions, .. } = &mut self.batch;
+		let mut calls: Vec<Call> = Vec::new();
+		for s in submissions.iter() {
+			let fits = |c: &&mut Call| c.view == s.key.view && c.material == s.key.material && c.quads == s.key.instanced && c.mode == s.key.mode && c.epoch == s.epoch
+				&& c.base == s.base && s.key.texture.is_none_or(|t| c.textures.contains(&t) || c.textures.len() < slots);
+			let call = match calls.last_mut().filter(fits) {
+				Some(call) => call,
+				None => {
+					calls.push(Call { view: s.key.view, material: s.key.material, quads: s.key.instanced, mode: s.key.mode, epoch: s.epoch, base: s.base, textures: Vec::new(),
+						start: s.start, end: s.start });
+					calls.last_mut().unwrap()
+				}
+			};
+			let slot = match s.key.texture {
+				Some(t) => match call.textures.iter().position(|c| *c == t) {
+					Some(slot) => slot as i32,
+					None => { call.textures.push(t); call.textures.len() as i32 - 1 }
+				},
+				None => NO_TEXTURE,
+			};
+			if s.key.instanced {
+				for instance in &mut instances[s.start..s.end] { instance.slot = slot; }
+			} else {
+				for &i in &indexdata[s.start..s.end] { shapedata[i as usize].slot = slot; }
+			}
+			call.end = s.end;
+		}
+		calls
+	}
+
+	// As many slots as the default program has samplers for and GL lets fragment shaders (and everything together) use, then points
+	// each sampler at its unit (a unit, not a texture, those get bound to a unit)
+	fn texture_slots(&mut self) -> Result<()> {
+		let Some(program) = self.program else { return Ok(()); };
+		let samplers = self.shaders.get(program)?.reflection.uniforms.get("u_tex").map_or(1, |u| u.size as usize);
+		let units = unsafe { self.gl.get_parameter_i32(glow::MAX_TEXTURE_IMAGE_UNITS).min(self.gl.get_parameter_i32(glow::MAX_COMBINED_TEXTURE_IMAGE_UNITS)) };
+		self.slots = samplers.min(units.max(1) as usize);
+		for slot in 0..self.slots {
+			self.apply(None, &format!("u_tex[{}]", slot), UniformValue::Sampler(slot as i32))?;
+		}
+		Ok(())
+	}
+
+	// Starts reloading the shaders from this file whenever it (or anything it pulls in) changes, setup does this in debug builds. What
+	// it includes is watched from the start, a file that doesn't parse yet just gets its includes picked up by the first reload that works
+	pub fn watch_shaders(&mut self, path: &str) {
+		let mut w = ShaderWatcher::new(path);
+		if let Ok(src) = std::fs::read_to_string(path).map_err(RendererError::from).and_then(|text| ShaderSource::parse(path, &text)) {
+			w.watch(&src.files);
+		}
+		self.watcher = Some(w);
+	}
+
+	// Rebuilds the shaders if their files changed, returning whether the program got swapped. A broken edit is printed and kept in
+	// shader_error, and drawing carries on with the program that was there before
+	pub fn reload_shaders(&mut self) -> bool {
+		let Some(w) = self.watcher.as_mut() else { return false; };
+		if !w.changed() { return false; }
+		let path = w.path.clone();
+		match self.swap_shaders(&path) {
+			Ok(()) => { self.shader_error = None; true }
+			Err(e) => {
+				eprintln!("Keeping the old shaders: {}", e);
+				self.shader_error = Some(e);
+				false
+			}
+		}
+	}
 
-		// Compiles vertex and fragment shaders
-		let vs = compile(glow::VERTEX_SHADER, vert);
-		let fs = compile(glow::FRAGMENT_SHADER, frag);
+	fn swap_shaders(&mut self, path: &str) -> Result<()> {
+		let src = ShaderSource::parse(path, &std::fs::read_to_string(path)?)?;
+		if let Some(w) = self.watcher.as_mut() { w.watch(&src.files); }
 
-		// Links symbols with error checking
-		self.gl.link_program(program);
-		if !self.gl.get_program_link_status(program) {
-			panic!("Error in linking programs: {}", self.gl.get_program_info_log(program));
+		// Every variant gets rebuilt from the new source under the handle it already had, since materials hang on to theirs. Nothing
+		// changes until they've all built, and the ones that did get thrown away if one didn't
+		let mut wanted: Vec<(Defines, Option<ShaderHandle>)> = self.variants.iter().map(|(d, h)| (d.clone(), Some(*h))).collect();
+		if !self.variants.contains_key(&Defines::new()) { wanted.push((Defines::new(), None)); }
+		let mut built = Vec::new();
+		for (defines, _) in &wanted {
+			match self.build(&src, defines) {
+				Ok(program) => built.push(program),
+				Err(e) => {
+					for program in built { unsafe { self.gl.delete_program(program); } }
+					return Err(e);
+				}
+			}
 		}
 
-		// Deletes shaders and exits
-		self.gl.delete_shader(vs);
-		self.gl.delete_shader(fs);
+		for ((defines, handle), program) in wanted.into_iter().zip(built) {
+			let program = unsafe { Program::new(&self.gl, program) };
+			match handle {
+				Some(handle) => {
+					let old = std::mem::replace(self.shaders.get_mut(handle)?, program);
+					unsafe { self.gl.delete_program(old.native); }
+				}
+				None => { let handle = self.shaders.insert(program); self.variants.insert(defines, handle); }
+			}
+		}
+		self.source = Some(src);
+		self.program = self.variants.get(&Defines::new()).copied();
+
+		// The new programs start out with every uniform zeroed. Ones that don't fit anymore are left that way, the next set_uniform will say why
+		self.bind(None, false)?;
+		self.texture_slots()?;
+		Ok(())
+	}
+
+	fn instanced_defines() -> Defines { defines(&[("INSTANCED", "1")]) }
+
+	// The program for the loaded shader file with these defines added, built the first time it's asked for
+	pub fn variant(&mut self, defines: &Defines) -> Result<ShaderHandle> {
+		if let Some(handle) = self.variants.get(defines) { return Ok(*handle); }
+		let src = self.source.as_ref().ok_or_else(|| RendererError::ResourceCreation("shader variant (no shader file loaded yet)".into()))?;
+		let program = self.build(src, defines)?;
+		let handle = self.shaders.insert(unsafe { Program::new(&self.gl, program) });
+		self.variants.insert(defines.clone(), handle);
+		Ok(handle)
+	}
+
+	// Path is only for the error messages, which point at lines of the file rather than of each stage
+	pub fn load_shaders(&self, path: &str, text: &str) -> Result<glow::Program> {
+		self.build(&ShaderSource::parse(path, text)?, &Defines::new())
+	}
+
+	pub fn build(&self, src: &ShaderSource, defines: &Defines) -> Result<glow::Program> {
+		unsafe {
+			// Creates a new program so we can return
+			let program = self.gl.create_program().map_err(|e| RendererError::ResourceCreation(format!("shader program ({})", e)))?;
+
+			// Compiles shaders
+			let compile = |stage: ShaderStage| -> Result<glow::Shader> {
+				let s = src.stage(stage, defines).unwrap();
+				let shader = self.gl.create_shader(stage.gl_type()).map_err(|e| RendererError::ResourceCreation(format!("{:?} shader ({})", stage, e)))?;
+				self.gl.shader_source(shader, &s.source);
+				self.gl.compile_shader(shader);
+				if !self.gl.get_shader_compile_status(shader) {
+					let log = self.gl.get_shader_info_log(shader);
+					self.gl.delete_shader(shader);
+					return Err(RendererError::ShaderCompile { stage, diagnostics: src.diagnostics(&s, &log), log });
+				}
+				self.gl.attach_shader(program, shader);
+
+				Ok(shader)
+			};
+
+			// Compiles every stage, cleaning up whatever got made if one doesn't
+			let mut shaders = Vec::new();
+			for stage in src.stages() {
+				match compile(stage) {
+					Ok(shader) => shaders.push(shader),
+					Err(e) => {
+						for shader in shaders { self.gl.delete_shader(shader); }
+						self.gl.delete_program(program);
+						return Err(e);
+					}
+				}
+			}
+
+			// Links symbols with error checking
+			self.gl.link_program(program);
+			let linked = self.gl.get_program_link_status(program);
+
+			// Deletes shaders and exits
+			for shader in shaders { self.gl.delete_shader(shader); }
+			if !linked {
+				let log = self.gl.get_program_info_log(program);
+				self.gl.delete_program(program);
+				return Err(RendererError::Link(log));
+			}
 
-		program
+			Ok(program)
+		}
 	}
 }
 
-impl GraphicsAPI for GLContext {
-	unsafe fn setup(&mut self) -> &mut dyn GraphicsAPI {
+// The blending each mode draws with. Alpha mixes by the source alpha the way everything always has, Add puts the source on top of
+// what's there, and Multiply scales what's there by the source
+fn blend_func(gl: &glow::Context, mode: BlendMode) {
+	unsafe {
+		match mode {
+			BlendMode::Alpha => gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_COLOR),
+			BlendMode::Add => gl.blend_func(glow::SRC_ALPHA, glow::ONE),
+			BlendMode::Multiply => gl.blend_func(glow::DST_COLOR, glow::ZERO),
+		}
+	}
+}
 
-		// Debug and other basic stuffs
-		self.gl.debug_message_callback(|_: u32, _: u32, _: u32, _: u32, msg: &str| println!("{}", msg));
-		self.gl.enable(glow::BLEND);
-		self.gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_COLOR);
+impl GraphicsAPI for GLContext {
+	fn setup(&mut self) -> Result<&mut dyn GraphicsAPI> {
+		unsafe {
+			// Debug and other basic stuffs
+			self.gl.debug_message_callback(|_: u32, _: u32, _: u32, _: u32, msg: &str| println!("{}", msg));
+			self.gl.enable(glow::BLEND);
+			blend_func(&self.gl, BlendMode::Alpha);
 
-		// Creates a vertex array and loads shaders
-		self.va = Some(self.gl.create_vertex_array().expect("bruh why won't VA form"));
+			// Creates a vertex array and loads shaders
+			self.va = Some(self.gl.create_vertex_array().map_err(|e| RendererError::ResourceCreation(format!("vertex array ({})", e)))?);
 
-		// Binds the vertex array so we can put the layout on it
-		self.gl.bind_vertex_array(self.va);
+			// Binds the vertex array so we can put the layout on it
+			self.gl.bind_vertex_array(self.va);
+		}
 
 		// Compiles shaders
-		if cfg!(debug_assertions) {
-			// Reads file dynamically if in debug mode, so we don't have to recompile when editing shaders
-			self.program = Some(self.load_shaders(std::fs::read_to_string(r".\res\shaders.glsl").unwrap().as_str()))
+		self.source = Some(if cfg!(debug_assertions) {
+			// Reads file dynamically if in debug mode, so we don't have to recompile when editing shaders (or even restart)
+			self.watch_shaders("res/shaders.glsl");
+			ShaderSource::parse("res/shaders.glsl", std::fs::read_to_string("res/shaders.glsl")?.as_str())?
 		} else {
-			self.program = Some(self.load_shaders(include_str!("../../res/shaders.glsl")));
+			ShaderSource::parse("res/shaders.glsl", include_str!("../../res/shaders.glsl"))?
+		});
+		let program = self.variant(&Defines::new())?;
+		self.program = Some(program);
+
+		unsafe {
+			// I FORGOT THIS INITIALLY LOL WTF
+			self.gl.use_program(Some(self.shaders.get(program)?.native));
+
 		}
 
-		// I FORGOT THIS INITIALLY LOL WTF
-		self.gl.use_program(self.program);
+		// The buffers (and the layout on them) get made by the first draw
+		let mode = StreamMode::detect(&self.gl);
+		self.vb = StreamBuffer::new(glow::ARRAY_BUFFER, self.format.layout().stride as usize, mode);
+		self.ib = StreamBuffer::new(glow::ELEMENT_ARRAY_BUFFER, core::mem::size_of::<u32>(), mode);
+		self.instances = StreamBuffer::new(glow::ARRAY_BUFFER, core::mem::size_of::<Instance>(), mode);
 
-		self.vb = self.gl.create_buffer().ok();
-		self.ib = self.gl.create_buffer().ok();
-		self.gl.bind_buffer(glow::ARRAY_BUFFER, self.vb);
-		self.gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, self.ib);
-		self.set_texture(0);
+		// The unit quad goes on a vertex array of its own, the instance attributes get pointed at wherever each draw's instances are
+		if GLContext::can_instance(&self.gl) {
+			self.instanced = Some(self.variant(&GLContext::instanced_defines())?);
+			unsafe {
+				self.iva = Some(self.gl.create_vertex_array().map_err(|e| RendererError::ResourceCreation(format!("vertex array ({})", e)))?);
+				self.unit_quad = Some(self.gl.create_buffer().map_err(|e| RendererError::ResourceCreation(format!("unit quad ({})", e)))?);
+				self.gl.bind_vertex_array(self.iva);
+				self.gl.bind_buffer(glow::ARRAY_BUFFER, self.unit_quad);
+				self.gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, as_bytes(&[0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0]), glow::STATIC_DRAW);
+				Layout::new().addf(2).apply(&self.gl);
+				self.gl.bind_vertex_array(self.va);
+			}
+		}
 
-		// Makes a new layout, and then adds it thru gl attrib array ptrs
-		Layout::new().addf(2).addf(2).addf(4).apply(&self.gl); // (apply comes last because we need the stride)
-		self
+		self.texture_slots()?;
+		Ok(self)
 	}
 
-	unsafe fn draw(&mut self) {
-		if self.shapedata.len() == 0 { return; }
+	fn draw(&mut self) -> Result<()> {
+		self.reload_shaders();
+		let mut changes = std::mem::take(&mut self.queue);
+		let (mut at, end) = (0, changes.len());
+		self.material = None;
+		self.layer = 0;
+		let views = self.batch.end_frame(&self.screen);
+		self.projection = views[0].projection;
+		self.draw_calls = 0;
+		if self.batch.is_empty() {
+			self.seek(&mut changes, &mut at, end)?;
+			if self.active.is_some() || self.quads { self.bind(None, false)?; }
+			return Ok(());
+		}
+		self.batch.sort();
+		let calls = self.calls();
+
+		// Uploads, and the layout goes on again whenever the vertices end up in a new buffer (apply comes last because we need the stride).
+		// Binding the default program first puts the projection in, in case the screen changed
+		self.bind(None, false)?;
+		self.format.write(&self.batch.shapedata, &mut self.packed_vertices);
+		self.pack_indices();
+		let vertices = self.vb.upload(&self.gl, &self.packed_vertices)?;
+		if vertices.moved { unsafe { self.vertex_layout().apply(&self.gl); } }
+		let indices = self.ib.upload(&self.gl, &self.packed_indices)?;
+		let instances = self.instances.upload(&self.gl, as_bytes(&self.batch.instances))?;
+		let base = vertices.offset / self.format.layout().stride as usize;
+		unsafe { self.clear(); }
 
-		// Vertex data upload
-		self.gl.bind_buffer(glow::ARRAY_BUFFER, self.vb);
+		// Every call gets the uniforms, program, blending and textures it was batched with, and the default program and blending are left
+		// set after
+		let (mut mode, mut view) = (BlendMode::Alpha, 0);
+		for call in calls {
+			self.seek(&mut changes, &mut at, call.epoch)?;
+
+			// Another view is another viewport, and another projection for whatever's bound
+			if call.view != view {
+				view = call.view;
+				let [x, y, w, h] = views[view].viewport;
+				self.projection = views[view].projection;
+				unsafe { self.gl.viewport(x, self.screen.size.y as i32 - y - h, w, h); }
+				self.bind(call.material, call.quads)?;
+			}
+			if call.material != self.active || call.quads != self.quads { self.bind(call.material, call.quads)?; }
+			if call.mode != mode { mode = call.mode; blend_func(&self.gl, mode); }
+			for (unit, texture) in call.textures.iter().enumerate() {
+				let texture = *self.textures.get(*texture)?;
+				unsafe {
+					self.gl.active_texture(glow::TEXTURE0 + unit as u32);
+					self.gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+				}
+			}
+			if call.quads {
+				self.draw_instances(call.start, call.end, instances.offset);
+			} else {
+				self.draw_elements(call.start, call.end, (base + call.base) as i32, indices.offset);
+			}
+		}
+		if mode != BlendMode::Alpha { blend_func(&self.gl, BlendMode::Alpha); }
+		self.vb.fence(&self.gl);
+		self.ib.fence(&self.gl);
+		self.instances.fence(&self.gl);
+		self.seek(&mut changes, &mut at, end)?;
+		if self.active.is_some() || self.quads { self.bind(None, false)?; }
+		// for i in 0..self.batch.shapedata.len() { println!("{}: {:?}", i, &self.batch.shapedata[i]); }
+		self.batch.clear();
+		Ok(())
+	}
 
-		// If vertex data length used to be under the size required for the data, make it bigger
-		if self.prev_shp_size < self.shapedata.len() {
-			self.gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, core::slice::from_raw_parts(self.shapedata.as_ptr() as *const u8,
-				self.shapedata.len() * core::mem::size_of::<ShapeData>()), glow::STATIC_DRAW);
-			self.prev_shp_size = self.shapedata.len();
-		} else if self.shapedata.len() > 0 {
-			self.gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, 0, core::slice::from_raw_parts(self.shapedata.as_ptr() as *const u8,
-				self.shapedata.len() * core::mem::size_of::<ShapeData>()));
+	fn destroy(&mut self) -> Result<()> {
+		self.program = None;
+		self.queue.clear();
+		self.material = None;
+		self.active = None;
+		self.quads = false;
+		self.instanced = None;
+		self.layer = 0;
+		self.translucent.clear();
+		self.vb.destroy(&self.gl);
+		self.ib.destroy(&self.gl);
+		self.instances.destroy(&self.gl);
+		self.variants.clear();
+		self.materials.drain();
+		self.fonts.drain();
+		unsafe {
+			if let Some(va) = self.va.take() { self.gl.delete_vertex_array(va); }
+			if let Some(iva) = self.iva.take() { self.gl.delete_vertex_array(iva); }
+			if let Some(quad) = self.unit_quad.take() { self.gl.delete_buffer(quad); }
+			for program in self.shaders.drain() { self.gl.delete_program(program.native); }
+			for texture in self.textures.drain() { self.gl.delete_texture(texture); }
+			if let Some(fbo) = self.fbo.take() { self.gl.delete_framebuffer(fbo); }
+			if let Some(rb) = self.fbo_color.take() { self.gl.delete_renderbuffer(rb); }
 		}
+		Ok(())
+	}
 
-		// Index buffer upload
-		self.gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, self.ib);
-		if self.prev_ind_size < self.indexdata.len() {
-			self.gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, core::slice::from_raw_parts(self.indexdata.as_ptr() as *const u8,
-				self.indexdata.len() * core::mem::size_of::<u32>()), glow::STATIC_DRAW);
-			self.prev_ind_size = self.indexdata.len();
-		} else if self.indexdata.len() > 0 {
-			self.gl.buffer_sub_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, 0, core::slice::from_raw_parts(self.indexdata.as_ptr() as *const u8,
-				self.indexdata.len() * core::mem::size_of::<u32>()));
+	fn rect(&mut self, x: f32, y: f32, w: f32, h: f32) -> Result<()> {
+		for ([x, y, w, h], color) in self.styles.current.rect(x, y, w, h) {
+			self.quad(Instance::new(Vec2::new(x, y), Vec2::new(w, h), color), None)?;
 		}
+		Ok(())
+	}
 
-		self.gl.clear(glow::COLOR_BUFFER_BIT);
-		self.gl.draw_elements(glow::TRIANGLES, self.indexdata.len() as i32, glow::UNSIGNED_INT, 0);
-		// for i in 0..self.shapedata.len() { println!("{}: {:?}", i, &self.shapedata[i]); }
-		self.shapedata.clear();
-		self.indexdata.clear();
+	fn layer(&mut self, layer: i32) -> Result<()> {
+		self.layer = layer;
+		Ok(())
+	}
+
+	fn style(&self) -> Style { self.styles.current }
+	fn set_style(&mut self, style: Style) -> Result<()> {
+		self.styles.current = style;
+		Ok(())
+	}
+	fn push_style(&mut self) -> Result<()> {
+		self.styles.push();
+		Ok(())
+	}
+	fn pop_style(&mut self) -> Result<()> { self.styles.pop("pop_style") }
+
+	fn transform(&self) -> Transform { self.batch.transforms.current }
+	fn set_transform(&mut self, transform: Transform) -> Result<()> {
+		self.batch.transforms.current = transform;
+		Ok(())
+	}
+	fn push_matrix(&mut self) -> Result<()> {
+		self.batch.transforms.push();
+		Ok(())
+	}
+	fn pop_matrix(&mut self) -> Result<()> { self.batch.transforms.pop("pop_matrix") }
+
+	fn camera(&self) -> Option<Camera2D> { self.batch.camera() }
+	fn set_camera(&mut self, camera: Option<Camera2D>) -> Result<()> {
+		self.batch.set_camera(camera);
+		Ok(())
+	}
+
+	fn screen(&self) -> Screen { self.screen }
+	fn set_screen(&mut self, screen: Screen) -> Result<()> {
+
+		// Without a window the framebuffer is the screen, so it has to change size with it
+		if screen.size != self.screen.size && self.fbo_color.is_some() {
+			unsafe {
+				self.gl.bind_renderbuffer(glow::RENDERBUFFER, self.fbo_color);
+				self.gl.renderbuffer_storage(glow::RENDERBUFFER, glow::RGBA8, screen.size.x as i32, screen.size.y as i32);
+			}
+		}
+		self.screen = screen;
+		Ok(())
 	}
 
-	unsafe fn destroy(&mut self) {
-		self.gl.delete_buffer(self.vb.unwrap());
-		self.gl.delete_buffer(self.ib.unwrap());
-		self.gl.delete_vertex_array(self.va.unwrap());
-		self.gl.delete_program(self.program.unwrap());
+  fn load_image(&mut self, file: &str) -> Result<TextureHandle> {
+		self.texture(&Tex::load(file)?)
 	}
 
-	unsafe fn rect(&mut self, x: f32, y: f32, w: f32, h: f32) {
-		self.push_shape(Vec::<Vec2<f32>>::from([
-			Vec2::<f32> { x, y },
-			Vec2::<f32> { x: x + w, y },
-			Vec2::<f32> { x, y: y + h },
-			Vec2::<f32> { x: x + w, y: y + h },
-		]), vec![0, 1, 2, 2, 1, 3], [1.0, 0.0, 0.0, 1.0]);
+	fn image(&mut self, image: TextureHandle, x: i32, y: i32, w: i32, h: i32) -> Result<()> {
+		// Color alpha is how much the vertex color covers the texture, so images only get any of it when tinted
+		self.quad(Instance::new(Vec2::new(x as f32, y as f32), Vec2::new(w as f32, h as f32), self.styles.current.image_color()), Some(image))
 	}
 
-  unsafe fn load_image(&mut self, file: &str) -> Result<u32, image::ImageError> {
-    let img_raw = image::io::Reader::open(file)?.decode()?; // stupid rust rules.. why tf do you need to drop values bruh just keep them around so i don't need random unnecessary variables and spend like 10 mins figuring out stupid compiler messages
-    let img = img_raw.as_rgb8().unwrap(); // rust so stupid sometimes ughhhhhh
-		Ok(self.texture(img.to_vec(), img.width() as i32, glow::RGB as i32))
+	fn free_texture(&mut self, texture: TextureHandle) -> Result<()> {
+		let native = self.textures.remove(texture)?;
+		self.translucent.remove(&texture);
+		unsafe { self.gl.delete_texture(native); }
+		Ok(())
 	}
 
-	unsafe fn image(&mut self, image: u32, x: i32, y: i32, w: i32, h: i32) {
-		self.set_texture(image as i32);
-		if self.iva.is_none() { self.iva = self.gl.create_vertex_array().ok(); self.ivb = self.gl.create_buffer().ok(); }
-		let data = self.convert_screencoords(vec![Vec2::<i32> { x, y }, Vec2 { x:x+w, y }, Vec2 { x, y:y+h }, Vec2 { x:x+h, y:y+h } ]);
-		let upload = vec![ShapeData::new(data[0]), ShapeData::new(data[1]), ShapeData::new(data[2]), ShapeData::new(data[2]), ShapeData::new(data[3]), ShapeData::new(data[1])];
-		self.gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, 0, core::slice::from_raw_parts(upload.as_ptr() as *const u8, core::mem::size_of::<ShapeData>() * 6));
-		self.gl.draw_elements(glow::TRIANGLES, 4, glow::UNSIGNED_INT, 0);
-		self.set_texture(0);
+	fn load_font(&mut self) -> Result<FontHandle> {
+		let mut atlas = FontAtlas::new();
+		atlas.load("default", "./res/font.ttf")?;
+		let texture = self.texture(&atlas.tex)?;
+
+		// The atlas is coverage in the red channel. Reading it as white that much there lets the fill color it like a shape
+		unsafe {
+			self.gl.bind_texture(glow::TEXTURE_2D, Some(*self.textures.get(texture)?));
+			for (channel, from) in [(glow::TEXTURE_SWIZZLE_R, glow::ONE), (glow::TEXTURE_SWIZZLE_G, glow::ONE), (glow::TEXTURE_SWIZZLE_B, glow::ONE), (glow::TEXTURE_SWIZZLE_A, glow::RED)] {
+				self.gl.tex_parameter_i32(glow::TEXTURE_2D, channel, from as i32);
+			}
+		}
+		self.translucent.insert(texture);
+		Ok(self.fonts.insert(LoadedFont { atlas, texture }))
 	}
 
-	unsafe fn load_font(&mut self) -> u32 {
-		todo!();
+	fn free_font(&mut self, font: FontHandle) -> Result<()> {
+		let font = self.fonts.remove(font)?;
+		self.free_texture(font.texture)
 	}
 }
 
@@ -353,3 +1105,516 @@ impl GraphicsAPI for GLContext {
 // 		for i in gl.shapedata.iter() { print!("{:?}", i); }
 // 	}
 // }
+
+// A context for the tests to draw into, or None (and a note why) where there's no GL to be had
+#[cfg(all(test, target_os = "linux"))]
+pub(crate) fn headless(w: u32, h: u32) -> Option<GLContext> {
+	GLContext::headless(w, h).map_err(|e| eprintln!("No headless GL here, skipping: {}", e)).ok()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_headless() {
+	use crate::graphics::soft::SoftContext;
+	let Some(mut g) = headless(64, 64) else { return; };
+	g.setup().unwrap();
+	g.rect(32.0, 16.0, 16.0, 16.0).unwrap();
+	g.rect(0.0, 56.0, 16.0, 8.0).unwrap();
+	g.draw().unwrap();
+
+	// Has to come out pixel for pixel the same as the software renderer
+	let mut soft = SoftContext::new(64, 64);
+	soft.rect(32.0, 16.0, 16.0, 16.0).unwrap();
+	soft.rect(0.0, 56.0, 16.0, 8.0).unwrap();
+	soft.draw().unwrap();
+	assert_eq!(g.frame().texel(40, 20), [255, 0, 0, 255]);
+	assert!(g.frame().data == soft.frame().data);
+
+	// Handles die with the GL objects behind them
+	let tex = g.texture(&Tex::new(1, 1, Channels::RGB)).unwrap();
+	g.free_texture(tex).unwrap();
+	assert!(matches!(g.image(tex, 0, 0, 1, 1), Err(RendererError::StaleHandle(_))));
+	let shader = g.program.unwrap();
+	g.destroy().unwrap();
+	assert!(matches!(g.free_shader(shader), Err(RendererError::StaleHandle(_))));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_styles() {
+	use crate::graphics::soft::SoftContext;
+	let Some(mut g) = headless(64, 64) else { return; };
+	g.setup().unwrap();
+
+	// Strokes, blend modes and tints come out the same as the software renderer, and blending goes back to alpha after
+	let mut soft = SoftContext::new(64, 64);
+	for g in [&mut g as &mut dyn GraphicsAPI, &mut soft] {
+		g.fill([0.0, 1.0, 0.0, 1.0]).unwrap();
+		g.stroke([1.0, 1.0, 1.0, 1.0]).unwrap();
+		g.stroke_weight(4.0).unwrap();
+		g.rect(16.0, 16.0, 32.0, 32.0).unwrap();
+		g.push_style().unwrap();
+		g.layer(1).unwrap();
+		g.no_stroke().unwrap();
+		g.fill([1.0, 0.0, 0.0, 1.0]).unwrap();
+		g.blend_mode(BlendMode::Add).unwrap();
+		g.rect(24.0, 24.0, 16.0, 16.0).unwrap();
+		g.pop_style().unwrap();
+		g.draw().unwrap();
+	}
+	assert_eq!(g.frame().texel(32, 32), [255, 255, 0, 255]);
+	assert_eq!(g.frame().texel(16, 16), [255, 255, 255, 255]);
+	assert!(g.frame().data == soft.frame().data);
+
+	let mut tex = Tex::new(1, 1, Channels::RGB);
+	tex.set_texel(0, 0, [0, 0, 255, 255]);
+	let (a, b) = (g.texture(&tex).unwrap(), soft.texture(tex));
+	g.tint([1.0, 0.0, 0.0, 0.4]).unwrap();
+	soft.tint([1.0, 0.0, 0.0, 0.4]).unwrap();
+	g.image(a, 32, 16, 16, 16).unwrap();
+	soft.image(b, 32, 16, 16, 16).unwrap();
+	g.draw().unwrap();
+	soft.draw().unwrap();
+	assert_eq!(g.frame().texel(40, 24), [102, 0, 153, 255]);
+	assert!(g.frame().data == soft.frame().data);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_transforms() {
+	use crate::graphics::soft::SoftContext;
+	let Some(mut g) = headless(64, 64) else { return; };
+	g.setup().unwrap();
+
+	// Moved and scaled quads stay instances, sheared ones take vertices, and both land where the software renderer puts them
+	let mut soft = SoftContext::new(64, 64);
+	for g in [&mut g as &mut dyn GraphicsAPI, &mut soft] {
+		g.push_matrix().unwrap();
+		g.translate(16.0, 8.0).unwrap();
+		g.scale(16.0, 8.0).unwrap();
+		g.rect(0.0, 0.0, 1.0, 1.0).unwrap();
+		g.pop_matrix().unwrap();
+		g.translate(32.0, 48.0).unwrap();
+		g.shear(0.5, 0.0).unwrap();
+		g.rect(0.0, 0.0, 16.0, 16.0).unwrap();
+		assert!(g.pop_matrix().is_err());
+		g.draw().unwrap();
+	}
+	assert_eq!(g.draw_calls, 2);
+	assert_eq!(g.frame().texel(20, 14), [255, 0, 0, 255]);
+	assert_eq!(g.frame().texel(36, 14), [0, 0, 0, 0]);
+	assert!(g.frame().data == soft.frame().data);
+
+	// The transform doesn't outlast the frame
+	assert_eq!(g.transform(), Transform::IDENTITY);
+	g.translate(32.0, 32.0).unwrap();
+	g.rotate(std::f32::consts::FRAC_PI_2).unwrap();
+	g.rect(0.0, 0.0, 16.0, 8.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.frame().texel(28, 40), [255, 0, 0, 255]);
+	assert_eq!(g.frame().texel(40, 28), [0, 0, 0, 0]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_letterbox() {
+	use crate::graphics::api::Scaling;
+	use crate::graphics::soft::SoftContext;
+	let Some(mut g) = headless(64, 48) else { return; };
+	g.setup().unwrap();
+
+	// Bars, viewport and whatever strays off the edge come out the same as the software renderer, before and after a resize
+	let mut soft = SoftContext::new(64, 48);
+	for (w, h) in [(64, 48), (40, 64)] {
+		for g in [&mut g as &mut dyn GraphicsAPI, &mut soft] {
+			g.resize(w, h, 1.0).unwrap();
+			g.virtual_resolution(32, 32, Scaling::Fit).unwrap();
+			g.letterbox([0.0, 0.0, 1.0, 1.0]).unwrap();
+			g.rect(-8.0, 8.0, 48.0, 16.0).unwrap();
+			g.draw().unwrap();
+		}
+		assert!(g.frame().data == soft.frame().data, "{}x{}", w, h);
+	}
+	assert_eq!((g.frame().w, g.frame().h), (40, 64));
+	assert_eq!([g.frame().texel(20, 4), g.frame().texel(0, 24), g.frame().texel(20, 46)], [[0, 0, 255, 255], [255, 0, 0, 255], [0, 0, 0, 0]]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_cameras() {
+	use crate::graphics::soft::SoftContext;
+	let Some(mut g) = headless(64, 32) else { return; };
+	g.setup().unwrap();
+
+	// Split screen with a camera each, then the HUD over both of them without one. Whatever goes past a viewport gets cut off there
+	let mut soft = SoftContext::new(64, 32);
+	for g in [&mut g as &mut dyn GraphicsAPI, &mut soft] {
+		g.set_camera(Some(Camera2D { zoom: 2.0, viewport: Some([0.0, 0.0, 32.0, 32.0]), ..Camera2D::default() })).unwrap();
+		g.rect(-4.0, -4.0, 8.0, 8.0).unwrap();
+		g.set_camera(Some(Camera2D { viewport: Some([32.0, 0.0, 32.0, 32.0]), ..Camera2D::new(Vec2::new(100.0, 0.0)) })).unwrap();
+		g.rect(50.0, -100.0, 40.0, 200.0).unwrap();
+		g.set_camera(None).unwrap();
+		g.fill([0.0, 1.0, 0.0, 1.0]).unwrap();
+		g.rect(0.0, 30.0, 64.0, 2.0).unwrap();
+		g.draw().unwrap();
+		assert_eq!(g.camera(), None);
+	}
+	assert_eq!(g.draw_calls, 3);
+	let frame = g.frame();
+	assert_eq!([frame.texel(16, 16), frame.texel(31, 2), frame.texel(34, 2), frame.texel(40, 2), frame.texel(40, 31)],
+		[[255, 0, 0, 255], [0, 0, 0, 0], [255, 0, 0, 255], [0, 0, 0, 0], [0, 255, 0, 255]]);
+	assert!(frame.data == soft.frame().data);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_shader_errors() {
+	let Some(g) = headless(8, 8) else { return; };
+	let vert = "#version 330 core\nvoid main() { gl_Position = vec4(0.0); }\n";
+	assert!(matches!(g.load_shaders("test.glsl", vert), Err(RendererError::ShaderSyntax(_))));
+	assert!(matches!(g.load_shaders("test.glsl", &format!("{}# frag\n#version 330 core\nvoid nope() {{}}\n", vert)), Err(RendererError::Link(_))));
+
+	// Whatever the driver says, the error has to land on the right line of the whole file
+	let err = g.load_shaders("test.glsl", &format!("{}# frag\n#version 330 core\nvoid main() {{\n  nope();\n}}\n", vert)).unwrap_err();
+	let RendererError::ShaderCompile { stage: ShaderStage::Fragment, diagnostics, log } = &err else { panic!("{}", err) };
+	assert!(!diagnostics.is_empty(), "couldn't parse the driver log: {}", log);
+	assert_eq!((diagnostics[0].line, diagnostics[0].source.as_str()), (6, "  nope();"));
+	assert!(err.to_string().contains("test.glsl:6:"), "{}", err);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_hot_reload() {
+	use std::time::{Duration, SystemTime};
+	let Some(mut g) = headless(16, 16) else { return; };
+	g.setup().unwrap();
+	std::fs::create_dir_all("./target/hot_reload").unwrap();
+	let path = "./target/hot_reload/shaders.glsl";
+	let shaders = std::fs::read_to_string("res/shaders.glsl").unwrap();
+	let save = |path: &str, text: &str, secs: u64| {
+		std::fs::write(path, text).unwrap();
+		std::fs::File::options().write(true).open(path).unwrap().set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+	};
+	let line = "color = vec4(mix(s.xyz, v_col.xyz, v_col.a), s.a);";
+	let include = "./target/hot_reload/color.glsl";
+	save(include, line, 1);
+	save(path, &shaders.replace(line, "#include \"../target/hot_reload/color.glsl\""), 1);
+	g.watch_shaders(path);
+	g.watcher.as_mut().unwrap().interval = Duration::ZERO;
+	let before = g.program;
+	let variant = g.variant(&defines(&[("TINT", "1")])).unwrap();
+	let material = g.material(variant).unwrap();
+
+	// Saving only the included file is enough, before the main one's ever been reloaded
+	save(include, &line.replace("v_col.xyz", "v_col.yzx"), 2);
+	g.rect(0.0, 0.0, 16.0, 16.0).unwrap();
+	g.draw().unwrap();
+	assert!(g.shader_error.is_none());
+	assert_eq!(g.frame().texel(8, 8), [0, 0, 255, 255]);
+
+	// A broken save keeps drawing with the old program
+	save(path, &shaders.replace("v_col = col;", "v_col = nope;"), 3);
+	g.rect(0.0, 0.0, 16.0, 16.0).unwrap();
+	g.draw().unwrap();
+	assert!(matches!(g.shader_error, Some(RendererError::ShaderCompile { stage: ShaderStage::Vertex, .. })));
+	assert_eq!(g.program, before);
+	assert_eq!(g.frame().texel(8, 8), [0, 0, 255, 255]);
+
+	// Fixing it swaps the new programs in under the handles they already had, sampler and all, so materials keep drawing
+	save(path, &shaders.replace(line, "color = vec4(mix(s.xyz, v_col.zxy, v_col.a), s.a);"), 4);
+	g.rect(0.0, 0.0, 16.0, 16.0).unwrap();
+	g.draw().unwrap();
+	assert!(g.shader_error.is_none());
+	assert_eq!(g.program, before);
+	assert_eq!(g.frame().texel(8, 8), [0, 255, 0, 255]);
+	g.set_material(Some(material)).unwrap();
+	g.rect(0.0, 0.0, 16.0, 16.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.frame().texel(8, 8), [0, 255, 0, 255]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_shader_variants() {
+	use crate::graphics::shader::defines;
+	let Some(mut g) = headless(16, 16) else { return; };
+	g.setup().unwrap();
+
+	// Same defines in any order are the same program, and nothing defined is the one setup made
+	let a = g.variant(&defines(&[("A", "1"), ("B", "2")])).unwrap();
+	assert_eq!(g.variant(&defines(&[("B", "2"), ("A", "1")])).unwrap(), a);
+	assert_ne!(g.program.unwrap(), a);
+	assert_eq!(g.variant(&Defines::new()).unwrap(), g.program.unwrap());
+
+	// A geometry stage between the other two, passing triangles straight through
+	let geom = "#version 330 core\n# vert\nvoid main() { gl_Position = vec4(0.0); }\n# geom\nlayout (triangles) in;\nlayout (triangle_strip, max_vertices = 3) out;\n\
+		void main() { for (int i = 0; i < 3; i++) { gl_Position = gl_in[i].gl_Position; EmitVertex(); } EndPrimitive(); }\n# frag\nout vec4 color;\nvoid main() { color = vec4(1.0); }\n";
+	g.shader("geom.glsl", geom).unwrap();
+	assert!(matches!(g.shader("geom.glsl", &geom.replace("EmitVertex", "nope")), Err(RendererError::ShaderCompile { stage: ShaderStage::Geometry, .. })));
+}
+
+// Fills everything with whatever u_tint is, for the uniform, material and layer tests
+#[cfg(all(test, target_os = "linux"))]
+const TINTED: &str = "#version 330 core\n# vert\nlayout (location = 0) in vec2 pos;\nuniform mat3 u_projection;\nvoid main() { gl_Position = vec4((u_projection * vec3(pos, 1.0)).xy, 0.0, 1.0); }\n\
+	# frag\nuniform vec4 u_tint;\nout vec4 color;\nvoid main() { color = u_tint; }\n";
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_uniforms() {
+	let Some(mut g) = headless(16, 16) else { return; };
+	g.setup().unwrap();
+	let reflection = &g.shaders.get(g.program.unwrap()).unwrap().reflection;
+	assert_eq!(reflection.uniforms["u_tex"].gl_type, glow::SAMPLER_2D);
+	assert_eq!(reflection.attributes["pos"].location, 0);
+	assert_eq!((reflection.attributes["col"].location, reflection.attributes["col"].gl_type), (2, glow::FLOAT_VEC4));
+
+	// Wrong types are errors, wrong names only get warned about
+	assert!(matches!(g.set_uniform("u_tex", 1.0), Err(RendererError::UniformType { expected: "sampler", got: "float", .. })));
+	g.set_uniform("u_nope", 1.0).unwrap();
+	g.set_uniform("u_nope", 2.0).unwrap();
+	assert!(g.warned.contains("u_nope") && g.queue.is_empty());
+
+	// Each half gets drawn with the tint it was batched under
+	let shader = g.shader("tint.glsl", TINTED).unwrap();
+	g.program = Some(shader);
+	g.instanced = None;
+	unsafe { g.gl.use_program(Some(g.shaders.get(shader).unwrap().native)); }
+	g.set_uniform("u_tint", [1.0, 0.0, 0.0, 1.0]).unwrap();
+	g.rect(0.0, 0.0, 8.0, 16.0).unwrap();
+	g.set_uniform("u_tint", [0.0, 0.0, 1.0, 1.0]).unwrap();
+	g.rect(8.0, 0.0, 8.0, 16.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!((g.frame().texel(4, 8), g.frame().texel(12, 8)), ([255, 0, 0, 255], [0, 0, 255, 255]));
+	assert_eq!(g.uniforms["u_tint"], UniformValue::Vec4([0.0, 0.0, 1.0, 1.0]));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_materials() {
+	let Some(mut g) = headless(24, 8) else { return; };
+	g.setup().unwrap();
+	let shader = g.shader("tint.glsl", TINTED).unwrap();
+	let (green, blue) = (g.material(shader).unwrap(), g.material(shader).unwrap());
+	g.set_material_uniform(green, "u_tint", [0.0, 1.0, 0.0, 1.0]).unwrap();
+	g.set_material_uniform(blue, "u_tint", [0.0, 0.0, 1.0, 1.0]).unwrap();
+	assert!(matches!(g.set_material_uniform(blue, "u_tint", 1), Err(RendererError::UniformType { .. })));
+
+	// Default, then the two materials sharing a program, then back to the default, in one draw
+	g.rect(0.0, 0.0, 8.0, 8.0).unwrap();
+	g.set_material(Some(green)).unwrap();
+	g.rect(8.0, 4.0, 8.0, 4.0).unwrap();
+	g.set_material(Some(blue)).unwrap();
+	g.rect(8.0, 0.0, 8.0, 4.0).unwrap();
+	g.set_material(None).unwrap();
+	g.rect(16.0, 0.0, 8.0, 8.0).unwrap();
+	g.draw().unwrap();
+	let frame = g.frame();
+	assert_eq!([frame.texel(4, 4), frame.texel(12, 6), frame.texel(12, 2), frame.texel(20, 4)],
+		[[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 0, 0, 255]]);
+
+	// Whatever material was left on, the next frame starts back on the default
+	g.set_material(Some(green)).unwrap();
+	g.draw().unwrap();
+	g.rect(0.0, 0.0, 24.0, 8.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.frame().texel(12, 4), [255, 0, 0, 255]);
+
+	// Programs that want more than ShapeData has can't be materials
+	let extra = TINTED.replacen("void main()", "layout (location = 4) in float extra;\nvoid main()", 1).replace(".xy, 0.0, 1.0)", ".xy, extra, 1.0)");
+	let extra = g.shader("extra.glsl", &extra).unwrap();
+	assert!(matches!(g.material(extra), Err(RendererError::ResourceCreation(_))));
+	g.free_material(green).unwrap();
+	assert!(matches!(g.set_material(Some(green)), Err(RendererError::StaleHandle(_))));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_texture_slots() {
+	let Some(mut g) = headless(32, 8) else { return; };
+	g.setup().unwrap();
+	assert!(g.slots >= 2);
+	let solid = |px: [u8; 4]| { let mut t = Tex::new(1, 1, Channels::RGBA); t.set_texel(0, 0, px); t };
+	let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 0, 255]];
+	let textures: Vec<TextureHandle> = colors.iter().map(|&c| g.texture(&solid(c)).unwrap()).collect();
+
+	// A quarter of the screen across for each texture
+	let quad = |g: &mut GLContext, i: usize| {
+		let x = i as f32 * 8.0;
+		g.quad(Instance { uv: [0.5; 4], ..Instance::new(Vec2::new(x, 0.0), Vec2::new(8.0, 8.0), [1.0, 1.0, 1.0, 0.0]) }, Some(textures[i])).unwrap();
+	};
+
+	// Shapes and images in between each other, all of it in one draw call
+	for i in 0..4 { quad(&mut g, i); }
+	g.rect(0.0, 6.0, 4.0, 2.0).unwrap();
+	g.image(textures[0], 16, 4, 2, 2).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.draw_calls, 1);
+
+	// With two slots the textures have to go in two sets, and each quad still gets its own
+	g.slots = 2;
+	for i in 0..4 { quad(&mut g, i); }
+	g.draw().unwrap();
+	assert_eq!(g.draw_calls, 2);
+	let frame = g.frame();
+	let expected: Vec<[u8; 4]> = colors.to_vec();
+	assert_eq!((0..4).map(|i| frame.texel(i * 8 + 4, 4)).collect::<Vec<_>>(), expected);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_text() {
+	let Some(mut g) = headless(64, 32) else { return; };
+	g.setup().unwrap();
+	let font = g.load_font().unwrap();
+	let mut blue = Tex::new(1, 1, Channels::RGBA);
+	blue.set_texel(0, 0, [0, 0, 255, 255]);
+	let blue = g.texture(&blue).unwrap();
+
+	// Text in between a rect and an image samples the atlas from a slot like the image does, so it's all one draw call
+	g.rect(0.0, 24.0, 8.0, 8.0).unwrap();
+	g.set_style(Style { fill: Some([0.0, 1.0, 0.0, 1.0]), ..g.style() }).unwrap();
+	g.text(font, "hi", 0.0, 0.0, 24.0).unwrap();
+	g.image(blue, 56, 24, 8, 8).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.draw_calls, 1);
+	let frame = g.frame();
+	assert_eq!(frame.texel(4, 28), [255, 0, 0, 255]);
+	assert_eq!(frame.texel(60, 28), [0, 0, 255, 255]);
+
+	// The h's stems with nothing in between, and the i with its dot (edges are antialiased, so only mostly covered)
+	assert!([(2, 12), (10, 16), (16, 5), (16, 15)].iter().all(|&(x, y)| frame.texel(x, y)[1] > 200 && frame.texel(x, y)[3] > 200));
+	assert_eq!(frame.texel(6, 16), [0, 0, 0, 0]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_layers() {
+	let Some(mut g) = headless(16, 8) else { return; };
+	g.setup().unwrap();
+	let shader = g.shader("tint.glsl", TINTED).unwrap();
+	let tint = g.material(shader).unwrap();
+
+	// The left half goes on top even though it came first, and still gets the tint it was batched with
+	g.set_material(Some(tint)).unwrap();
+	g.set_material_uniform(tint, "u_tint", [1.0, 1.0, 1.0, 1.0]).unwrap();
+	g.layer(1).unwrap();
+	g.rect(0.0, 0.0, 8.0, 8.0).unwrap();
+	g.set_material_uniform(tint, "u_tint", [0.0, 0.0, 1.0, 1.0]).unwrap();
+	g.layer(0).unwrap();
+	g.rect(0.0, 0.0, 16.0, 8.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!((g.frame().texel(4, 4), g.frame().texel(12, 4)), ([255, 255, 255, 255], [0, 0, 255, 255]));
+	assert_eq!(g.materials.get(tint).unwrap().uniforms["u_tint"], UniformValue::Vec4([0.0, 0.0, 1.0, 1.0]));
+
+	// Draw puts the layer back to 0
+	assert_eq!(g.layer, 0);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_streaming() {
+	use crate::graphics::stream::MIN_CAPACITY;
+	let Some(mut g) = headless(16, 16) else { return; };
+	g.setup().unwrap();
+
+	// Whichever way the driver streams, frames keep coming out right while the buffer gets reused, grows and shrinks
+	let size = core::mem::size_of::<Instance>();
+	for mode in [g.instances.mode, StreamMode::Orphan] {
+		g.instances.destroy(&g.gl);
+		g.instances = StreamBuffer::new(glow::ARRAY_BUFFER, size, mode);
+		g.instances.shrink_after = 4;
+		for frame in 0..12 {
+			// A lot of particles for a couple of frames in the middle
+			let n = if frame == 3 || frame == 4 { 4000 } else { 1 };
+			for _ in 0..n { g.rect(4.0, 4.0, 8.0, 8.0).unwrap(); }
+			g.draw().unwrap();
+			assert_eq!((g.frame().texel(8, 8), g.frame().texel(1, 1)), ([255, 0, 0, 255], [0, 0, 0, 0]), "{:?} frame {}", mode, frame);
+		}
+		let stats = g.instances.stats;
+		assert_eq!((stats.frames, stats.bytes), (12, size));
+		assert_eq!((stats.reallocations, stats.capacity), (3, MIN_CAPACITY.div_ceil(size) * size));
+	}
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_instancing() {
+	let Some(mut g) = headless(16, 16) else { return; };
+	g.setup().unwrap();
+	assert!(g.instanced.is_some());
+
+	// A rect, and a wide bar turned on its end around its middle
+	let draw = |g: &mut GLContext| {
+		g.rect(0.0, 12.0, 4.0, 4.0).unwrap();
+		let bar = Instance::new(Vec2::new(7.6, 10.8), Vec2::new(8.8, 1.6), [0.0, 0.0, 1.0, 1.0]);
+		g.quad(Instance { rotation: std::f32::consts::FRAC_PI_2, ..bar }, None).unwrap();
+		g.draw().unwrap();
+		g.frame()
+	};
+	let instanced = draw(&mut g);
+	assert_eq!(g.draw_calls, 1);
+	assert_eq!([instanced.texel(2, 14), instanced.texel(12, 10), instanced.texel(12, 4), instanced.texel(14, 11)],
+		[[255, 0, 0, 255], [0, 0, 255, 255], [0, 0, 0, 0], [0, 0, 0, 0]]);
+
+	// Without an instanced program the same quads go in as vertices, and come out the same
+	g.instanced = None;
+	let fallback = draw(&mut g);
+	assert!(instanced.data == fallback.data);
+	assert_eq!(g.vb.stats.bytes, 8 * g.vertex_layout().stride as usize);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn gl_vertex_formats() {
+	let Some(mut g) = headless(16, 16) else { return; };
+	g.setup().unwrap();
+	g.instanced = None;
+	let mut tex = Tex::new(2, 2, Channels::RGB);
+	tex.set_texel(0, 0, [0, 255, 0, 255]);
+	tex.set_texel(1, 1, [0, 0, 255, 255]);
+	let tex = g.texture(&tex).unwrap();
+
+	// Every format draws the same thing, in less room
+	let full = VertexFormat { packed_colors: false, half_uvs: false };
+	let mut frames = Vec::new();
+	for format in [full, VertexFormat::default(), VertexFormat { packed_colors: true, half_uvs: true }] {
+		g.set_vertex_format(format);
+		g.rect(0.0, 8.0, 8.0, 8.0).unwrap();
+		g.image(tex, 8, 4, 4, 4).unwrap();
+		g.draw().unwrap();
+		frames.push(g.frame());
+		assert_eq!(g.vb.stats.bytes, 8 * format.layout().stride as usize);
+	}
+	assert_eq!(frames.iter().map(|f| f.texel(4, 12)).collect::<Vec<_>>(), [[255, 0, 0, 255]; 3]);
+	assert!(frames.iter().all(|f| f.data == frames[0].data));
+	assert_eq!([full, VertexFormat::default()].map(|f| f.layout().stride), [36, 24]);
+
+	// Past one segment of vertices the indices count from a new base and stay 16 bits
+	for _ in 0..20000 { g.rect(0.0, 15.0, 1.0, 1.0).unwrap(); }
+	g.rect(8.0, 0.0, 8.0, 8.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.draw_calls, 2);
+	assert_eq!(g.ib.stats.bytes, 20001 * 6 * 2);
+	assert_eq!(g.frame().texel(12, 4), [255, 0, 0, 255]);
+}
+
+#[test]
+fn gl_vertex_layouts() {
+	#[derive(VertexLayout)]
+	#[repr(C)]
+	#[allow(dead_code)]
+	struct ByteSlot { #[normalized] col: [u8; 3], slot: [u8; 1] }
+
+	// Derived layouts put every attribute where the compiler did, in field order
+	let shape = ShapeData::layout();
+	assert_eq!((0..shape.len()).map(|i| shape.offset(i).unwrap()).collect::<Vec<_>>(), [0, 8, 16, 32]);
+	assert_eq!(shape.attribute(2), Some((&OpenGLType::Float, 4)));
+	assert_eq!(shape.stride, 36);
+	let instance = GLContext::instance_layout();
+	assert_eq!(instance.attribute(5), Some((&OpenGLType::Char, 4)));
+	assert!(instance.normalized(5) && !instance.normalized(6));
+	assert!(!ByteSlot::layout().normalized(1) && ByteSlot::layout().normalized(0));
+	assert_eq!(instance.offset(5), Some(core::mem::offset_of!(Instance, col) as i32));
+	assert_eq!(Instance::layout().stride, core::mem::size_of::<Instance>() as i32);
+	assert_eq!([HalfVertex::layout().stride, HalfUvVertex::layout().stride], [20, 32]);
+}
@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+use crate::graphics::api::GraphicsAPI;
+use crate::graphics::tex::{Channels, Tex};
+use crate::graphics::error::Result;
+
+// Golden image tests: scenes get rendered, then checked against the pngs in res/golden.
+// Run with BLESS=1 to write the current output as the new references, and failures leave the actual and diff images in target/golden
+
+pub const GOLDEN_DIR: &str = "./res/golden";
+pub const OUT_DIR: &str = "./target/golden";
+
+// How far off a frame can be before it counts as different
+#[derive(Copy, Clone, Debug)]
+pub struct Tolerance {
+	pub channel: u8,      // per channel difference a pixel is allowed before it counts as differing
+	pub max_pixels: usize // how many differing pixels are let through
+}
+impl Tolerance {
+	pub const EXACT: Tolerance = Tolerance { channel: 0, max_pixels: 0 };
+}
+
+pub struct Scene {
+	pub name: &'static str,
+	pub w: usize,
+	pub h: usize,
+	pub draw: fn(&mut dyn GraphicsAPI) -> Result<()>,
+}
+
+pub const SCENES: &[Scene] = &[
+	Scene { name: "rects", w: 64, h: 48, draw: |g| {
+		g.rect(0.0, 42.0, 64.0, 6.0)?;
+		g.rect(8.0, 6.0, 16.0, 30.0)?;
+		g.rect(32.0, 12.0, 16.0, 12.0)?;
+		g.rect(40.0, 6.0, 16.0, 12.0)?;
+		g.draw()
+	} },
+	Scene { name: "image", w: 64, h: 48, draw: |g| {
+		let img = g.load_image("./res/bg.jpg")?;
+		g.image(img, 20, 14, 24, 20)?;
+
+		// Both are opaque, so without a layer of its own the rect could get sorted under the image
+		g.layer(1)?;
+		g.rect(24.0, 18.0, 16.0, 12.0)?;
+		g.draw()
+	} },
+];
+
+fn to_rgba(tex: &Tex) -> Tex {
+	if tex.channels == Channels::RGBA { return tex.clone(); }
+	let mut ret = Tex::new(tex.w, tex.h, Channels::RGBA);
+	for y in 0..tex.h {
+		for x in 0..tex.w { ret.set_texel(x, y, tex.texel(x, y)); }
+	}
+	ret
+}
+
+// Compares two frames, giving back how many pixels differ and an image of where
+pub fn compare(actual: &Tex, expected: &Tex, tol: Tolerance) -> (usize, Tex) {
+	let mut diff = Tex::new(expected.w, expected.h, Channels::RGBA);
+	let mut differing = 0;
+	for y in 0..expected.h {
+		for x in 0..expected.w {
+			let (a, e) = (actual.texel(x, y), expected.texel(x, y));
+			let delta = (0..4).map(|i| a[i].abs_diff(e[i])).max().unwrap();
+
+			// Differing pixels are red by how far off they are, the rest is the expected image faded out
+			if delta > tol.channel {
+				differing += 1;
+				diff.set_texel(x, y, [128 + delta / 2, 0, 0, 255]);
+			} else {
+				let grey = ((e[0] as u32 + e[1] as u32 + e[2] as u32) / 12) as u8;
+				diff.set_texel(x, y, [grey, grey, grey, 255]);
+			}
+		}
+	}
+	(differing, diff)
+}
+
+pub fn blessing() -> bool {
+	std::env::var("BLESS").is_ok_and(|v| v != "0")
+}
+
+// Checks a frame against res/golden/<name>.png, or writes it there when blessing
+pub fn check(name: &str, frame: &Tex, tol: Tolerance) -> std::result::Result<(), String> {
+	let path = PathBuf::from(GOLDEN_DIR).join(format!("{}.png", name));
+	let frame = to_rgba(frame);
+
+	if blessing() {
+		std::fs::create_dir_all(GOLDEN_DIR).map_err(|e| e.to_string())?;
+		frame.to_image().save(&path).map_err(|e| e.to_string())?;
+		println!("Blessed {}", path.display());
+		return Ok(());
+	}
+
+	let expected = match image::open(&path) {
+		Ok(img) => { let img = img.to_rgba8(); Tex { w: img.width() as usize, h: img.height() as usize, data: img.into_raw(), channels: Channels::RGBA } }
+		Err(e) => return Err(format!("{}: can't load reference ({}), run with BLESS=1 to make it", path.display(), e)),
+	};
+	if (expected.w, expected.h) != (frame.w, frame.h) {
+		return Err(format!("{}: frame is {}x{} but the reference is {}x{}", name, frame.w, frame.h, expected.w, expected.h));
+	}
+
+	let (differing, diff) = compare(&frame, &expected, tol);
+	if differing <= tol.max_pixels { return Ok(()); }
+
+	// Leaves what we got and where it's off around to look at
+	let out = PathBuf::from(OUT_DIR);
+	let _ = std::fs::create_dir_all(&out);
+	let _ = frame.to_image().save(out.join(format!("{}.actual.png", name)));
+	let _ = diff.to_image().save(out.join(format!("{}.diff.png", name)));
+	Err(format!("{}: {} pixels differ by more than {} (allowed {}), see {}", name, differing, tol.channel, tol.max_pixels, out.display()))
+}
+
+// Runs every scene through a backend, the closure makes the backend and hands back the frame after drawing.
+// The software renderer is what the references come from, so other backends get skipped while blessing
+pub fn check_scenes<G: GraphicsAPI>(prefix: &str, skip: &[&str], tol: Tolerance, mut make: impl FnMut(usize, usize) -> Option<G>, frame: impl Fn(&G) -> Tex) {
+	if blessing() && prefix != "soft" { return; }
+	let mut failures = Vec::<String>::new();
+	for scene in SCENES.iter().filter(|s| !skip.contains(&s.name)) {
+		let Some(mut g) = make(scene.w, scene.h) else { return; };
+		g.setup().unwrap();
+		(scene.draw)(&mut g).unwrap();
+		if let Err(e) = check(scene.name, &frame(&g), tol) { failures.push(format!("{} {}", prefix, e)); }
+		g.destroy().unwrap();
+	}
+	assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn golden_soft() {
+	use crate::graphics::soft::SoftContext;
+	check_scenes("soft", &[], Tolerance::EXACT, |w, h| Some(SoftContext::new(w, h)), |g| g.frame().clone());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn golden_gl() {
+	use crate::graphics::gl::headless;
+
+	check_scenes("gl", &[], Tolerance { channel: 2, max_pixels: 8 }, |w, h| headless(w as u32, h as u32), |g| g.frame());
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use crate::graphics::error::{RendererError, Result};
+
+// What a handle points at, only there so the handle types can't be mixed up
+pub enum Texture {}
+pub enum Font {}
+pub enum Shader {}
+pub enum Material {}
+
+// Index into a Pool plus the generation of the slot when it was handed out, so a handle to something that got freed (and maybe replaced) is caught instead of quietly pointing at the new thing
+pub struct Handle<T> {
+	index: u32,
+	generation: u32,
+	_kind: PhantomData<fn() -> T>,
+}
+pub type TextureHandle = Handle<Texture>;
+pub type FontHandle = Handle<Font>;
+pub type ShaderHandle = Handle<Shader>;
+pub type MaterialHandle = Handle<Material>;
+
+impl<T> Handle<T> {
+	pub fn index(&self) -> u32 { self.index }
+	pub fn generation(&self) -> u32 { self.generation }
+
+	// The error for using this after it's been freed
+	pub fn stale(self) -> RendererError { RendererError::StaleHandle(format!("{:?}", self)) }
+}
+
+// Derives would want T to implement all of these too
+impl<T> Copy for Handle<T> {}
+impl<T> Clone for Handle<T> {
+	fn clone(&self) -> Self { *self }
+}
+impl<T> PartialEq for Handle<T> {
+	fn eq(&self, other: &Self) -> bool { self.index == other.index && self.generation == other.generation }
+}
+impl<T> Eq for Handle<T> {}
+impl<T> Hash for Handle<T> {
+	fn hash<H: Hasher>(&self, state: &mut H) { self.index.hash(state); self.generation.hash(state); }
+}
+impl<T> fmt::Debug for Handle<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let kind = std::any::type_name::<T>().rsplit("::").next().unwrap_or("");
+		write!(f, "{}#{}v{}", kind, self.index, self.generation)
+	}
+}
+
+struct Slot<V> {
+	generation: u32,
+	value: Option<V>,
+}
+
+// Storage for whatever a backend keeps behind a handle. Freed slots get reused with the generation bumped
+pub struct Pool<T, V> {
+	slots: Vec<Slot<V>>,
+	free: Vec<u32>,
+	_kind: PhantomData<fn() -> T>,
+}
+
+impl<T, V> Default for Pool<T, V> {
+	fn default() -> Self { Self::new() }
+}
+
+impl<T, V> Pool<T, V> {
+	pub fn new() -> Self {
+		Pool { slots: Vec::new(), free: Vec::new(), _kind: PhantomData }
+	}
+
+	pub fn insert(&mut self, value: V) -> Handle<T> {
+		let index = match self.free.pop() {
+			Some(i) => { self.slots[i as usize].value = Some(value); i }
+			None => { self.slots.push(Slot { generation: 0, value: Some(value) }); (self.slots.len() - 1) as u32 }
+		};
+		Handle { index, generation: self.slots[index as usize].generation, _kind: PhantomData }
+	}
+
+	pub fn contains(&self, handle: Handle<T>) -> bool {
+		self.slots.get(handle.index as usize).is_some_and(|s| s.generation == handle.generation && s.value.is_some())
+	}
+
+	pub fn get(&self, handle: Handle<T>) -> Result<&V> {
+		match self.slots.get(handle.index as usize) {
+			Some(Slot { generation, value: Some(v) }) if *generation == handle.generation => Ok(v),
+			_ => Err(handle.stale()),
+		}
+	}
+
+	pub fn get_mut(&mut self, handle: Handle<T>) -> Result<&mut V> {
+		match self.slots.get_mut(handle.index as usize) {
+			Some(Slot { generation, value: Some(v) }) if *generation == handle.generation => Ok(v),
+			_ => Err(handle.stale()),
+		}
+	}
+
+	pub fn remove(&mut self, handle: Handle<T>) -> Result<V> {
+		self.get(handle)?;
+		let slot = &mut self.slots[handle.index as usize];
+		slot.generation = slot.generation.wrapping_add(1);
+		self.free.push(handle.index);
+		Ok(slot.value.take().unwrap())
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &V)> {
+		self.slots.iter().enumerate().filter_map(|(i, s)| s.value.as_ref().map(|v| (Handle { index: i as u32, generation: s.generation, _kind: PhantomData }, v)))
+	}
+
+	pub fn len(&self) -> usize { self.slots.len() - self.free.len() }
+	pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+	// Frees everything, handing the values back so the backend can clean them up
+	pub fn drain(&mut self) -> Vec<V> {
+		let mut ret = Vec::new();
+		for (i, slot) in self.slots.iter_mut().enumerate() {
+			if let Some(v) = slot.value.take() {
+				slot.generation = slot.generation.wrapping_add(1);
+				self.free.push(i as u32);
+				ret.push(v);
+			}
+		}
+		ret
+	}
+}
+
+#[test]
+fn stale_handles() {
+	let mut pool = Pool::<Texture, &str>::new();
+	let a = pool.insert("a");
+	let b = pool.insert("b");
+	assert_eq!(*pool.get(a).unwrap(), "a");
+	assert_eq!(pool.remove(a).unwrap(), "a");
+
+	// The freed slot gets reused, but the old handle still can't see into it
+	let c = pool.insert("c");
+	assert_eq!(c.index(), a.index());
+	assert!(matches!(pool.get(a), Err(RendererError::StaleHandle(_))));
+	assert!(pool.remove(a).is_err());
+	assert_eq!(*pool.get(c).unwrap(), "c");
+	assert_eq!(pool.len(), 2);
+
+	assert_eq!(pool.drain().len(), 2);
+	assert!(!pool.contains(b) && !pool.contains(c) && pool.is_empty());
+	assert_eq!(format!("{:?}", c), "Texture#0v1");
+}
@@ -0,0 +1,62 @@
+use khronos_egl as egl;
+
+// EGL_MESA_platform_surfaceless, gives us a display without any window system behind it
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+// An OpenGL context that isn't attached to any window, so GLContext can render on machines without a display (software Mesa works fine)
+pub enum HeadlessGL {
+	Egl { egl: Box<egl::DynamicInstance<egl::EGL1_5>>, display: egl::Display, context: egl::Context },
+	OsMesa(glutin::Context<glutin::PossiblyCurrent>),
+}
+
+impl HeadlessGL {
+
+	// Tries surfaceless EGL first, then OSMesa. The size only matters to OSMesa, EGL renders straight into our framebuffer
+	pub unsafe fn new(w: u32, h: u32) -> Result<Self, String> {
+		match HeadlessGL::egl() {
+			Ok(ctx) => Ok(ctx),
+			Err(e) => HeadlessGL::osmesa(w, h).map_err(|e2| format!("surfaceless EGL: {}, OSMesa: {}", e, e2)),
+		}
+	}
+
+	unsafe fn egl() -> Result<Self, String> {
+		let egl = egl::DynamicInstance::<egl::EGL1_5>::load_required().map_err(|e| e.to_string())?;
+		let display = egl.get_platform_display(PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE]).map_err(|e| e.to_string())?;
+		egl.initialize(display).map_err(|e| e.to_string())?;
+
+		// Surfaceless displays only have pbuffer configs, the default asks for window ones. Same 3.3 core the shaders are written against
+		let config = egl.choose_first_config(display, &[egl::SURFACE_TYPE, egl::PBUFFER_BIT, egl::RENDERABLE_TYPE, egl::OPENGL_BIT, egl::NONE]).map_err(|e| e.to_string())?
+			.ok_or("no OpenGL capable EGL config")?;
+		egl.bind_api(egl::OPENGL_API).map_err(|e| e.to_string())?;
+		let context = egl.create_context(display, config, None, &[
+			egl::CONTEXT_MAJOR_VERSION, 3, egl::CONTEXT_MINOR_VERSION, 3,
+			egl::CONTEXT_OPENGL_PROFILE_MASK, egl::CONTEXT_OPENGL_CORE_PROFILE_BIT, egl::NONE]).map_err(|e| e.to_string())?;
+		egl.make_current(display, None, None, Some(context)).map_err(|e| e.to_string())?;
+
+		Ok(HeadlessGL::Egl { egl: Box::new(egl), display, context })
+	}
+
+	unsafe fn osmesa(w: u32, h: u32) -> Result<Self, String> {
+		use glutin::platform::unix::HeadlessContextExt;
+		let ctx = glutin::ContextBuilder::new().with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+			.with_gl_profile(glutin::GlProfile::Core).build_osmesa(glutin::dpi::PhysicalSize::new(w, h)).map_err(|e| e.to_string())?;
+		Ok(HeadlessGL::OsMesa(ctx.make_current().map_err(|(_, e)| e.to_string())?))
+	}
+
+	pub fn get_proc_address(&self, s: &str) -> *const core::ffi::c_void {
+		match self {
+			HeadlessGL::Egl { egl, .. } => egl.get_proc_address(s).map_or(core::ptr::null(), |f| f as *const _),
+			HeadlessGL::OsMesa(ctx) => ctx.get_proc_address(s),
+		}
+	}
+}
+
+impl Drop for HeadlessGL {
+	fn drop(&mut self) {
+		if let HeadlessGL::Egl { egl, display, context } = self {
+			let _ = egl.make_current(*display, None, None, None);
+			let _ = egl.destroy_context(*display, *context);
+			let _ = egl.terminate(*display);
+		}
+	}
+}
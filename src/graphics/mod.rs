@@ -1,10 +1,34 @@
 
 // Graphics API methods definition
 pub mod api;
+pub mod camera;
+pub mod error;
+pub mod handle;
+
+// Vertex data shared between the batch renderers, and the buffers GL streams it through
+pub mod batch;
+pub mod stream;
 
 // Batch renderers written in their respective apis
 pub mod gl;
+#[cfg(target_os = "linux")]
+pub mod headless;
+pub mod soft;
+pub mod svg;
+pub mod term;
 //pub mod vulkan;
 
+// Backends that stand in for a renderer rather than drawing anything
+pub mod record;
+
 // Text/texture loading
 pub mod tex;
+
+// Shader files, split into stages and mapped back to when the driver complains
+pub mod shader;
+pub mod uniform;
+pub mod validate;
+
+// Reference image checks for the tests
+#[cfg(test)]
+pub mod golden;
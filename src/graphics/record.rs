@@ -0,0 +1,512 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::graphics::api::{BlendMode, GraphicsAPI, Scaling, Screen, Stack, Style, Transform, Vec2};
+use crate::graphics::camera::{Camera2D, Shake};
+use crate::graphics::error::{RendererError, Result};
+use crate::graphics::handle::{Pool, Texture, Font, TextureHandle, FontHandle};
+
+// Every call that can be made on a GraphicsAPI. Handles are written as ids local to the recording, so it reads the same whichever backend handed them out
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+	Setup,
+	Draw,
+	Destroy,
+	Rect { x: f32, y: f32, w: f32, h: f32 },
+	Layer { layer: i32 },
+	Style { style: Style },
+	PushStyle,
+	PopStyle,
+	Transform { transform: Transform },
+	PushMatrix,
+	PopMatrix,
+	Screen { screen: Screen },
+	Camera { camera: Option<Camera2D> },
+	LoadImage { file: String, id: u32 },
+	Image { image: u32, x: i32, y: i32, w: i32, h: i32 },
+	FreeTexture { id: u32 },
+	LoadFont { id: u32 },
+	FreeFont { id: u32 },
+}
+
+// Records everything asked of the renderer, optionally passing it all on to a real backend too
+#[derive(Default)]
+pub struct RecordContext {
+	pub commands: Vec<Command>,
+	pub inner: Option<Box<dyn GraphicsAPI>>,
+
+	// Kept here too so they can be asked for without a backend
+	styles: Stack<Style>,
+	transforms: Stack<Transform>,
+	screen: Screen,
+	camera: Option<Camera2D>,
+
+	// Recording ids of the handles that are alive, shared between images and fonts
+	textures: HashMap<TextureHandle, u32>,
+	fonts: HashMap<FontHandle, u32>,
+	next_id: u32,
+
+	// Where the handles come from when there's no backend to ask
+	own_textures: Pool<Texture, ()>,
+	own_fonts: Pool<Font, ()>,
+}
+
+impl RecordContext {
+	pub fn new() -> Self { Self::default() }
+	pub fn wrap(inner: Box<dyn GraphicsAPI>) -> Self {
+		RecordContext { screen: inner.screen(), inner: Some(inner), ..Default::default() }
+	}
+
+	fn next_id(&mut self) -> u32 {
+		self.next_id += 1;
+		self.next_id - 1
+	}
+
+	// Splits the recording up at every draw, so a snapshot can be taken per frame
+	pub fn frames(&self) -> Vec<&[Command]> {
+		let mut ret = Vec::new();
+		let mut start = 0;
+		for (i, c) in self.commands.iter().enumerate() {
+			if *c == Command::Draw { ret.push(&self.commands[start..=i]); start = i + 1; }
+		}
+		ret
+	}
+
+	pub fn to_text(&self) -> String { to_text(&self.commands) }
+}
+
+// One command per line, e.g. `rect 0.0 0.0 0.5 0.5` or `load_image "./res/bg.jpg" -> 0`. Floats use the shortest repr that reads back the same, so the output is stable to diff
+impl fmt::Display for Command {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Command::Setup => write!(f, "setup"),
+			Command::Draw => write!(f, "draw"),
+			Command::Destroy => write!(f, "destroy"),
+			Command::Rect { x, y, w, h } => write!(f, "rect {:?} {:?} {:?} {:?}", x, y, w, h),
+			Command::Layer { layer } => write!(f, "layer {}", layer),
+			Command::Style { style } => {
+				let color = |c: Option<[f32; 4]>| c.map_or("none".to_string(), |c| format!("{:?},{:?},{:?},{:?}", c[0], c[1], c[2], c[3]));
+				write!(f, "style {} {} {:?} {} {}", color(style.fill), color(style.stroke), style.stroke_weight, blend_name(style.blend), color(style.tint))
+			}
+			Command::PushStyle => write!(f, "push_style"),
+			Command::PopStyle => write!(f, "pop_style"),
+			Command::Transform { transform: t } => {
+				let [[a, c, e], [b, d, g], _] = t.m;
+				write!(f, "transform {:?} {:?} {:?} {:?} {:?} {:?}", a, b, c, d, e, g)
+			}
+			Command::PushMatrix => write!(f, "push_matrix"),
+			Command::PopMatrix => write!(f, "pop_matrix"),
+			Command::Screen { screen: s } => {
+				let size = s.virtual_size.map_or("none".to_string(), |v| format!("{}x{}", v.x, v.y));
+				let c = s.letterbox;
+				write!(f, "screen {} {} {:?} {} {} {:?},{:?},{:?},{:?}", s.size.x, s.size.y, s.scale, size, scaling_name(s.scaling), c[0], c[1], c[2], c[3])
+			}
+			Command::Camera { camera: None } => write!(f, "camera none"),
+			Command::Camera { camera: Some(c) } => {
+				let rect = |r: Option<[f32; 4]>| r.map_or("none".to_string(), |r| format!("{:?},{:?},{:?},{:?}", r[0], r[1], r[2], r[3]));
+				write!(f, "camera {:?} {:?} {:?} {:?} {} {} {:?},{:?},{:?}", c.target.x, c.target.y, c.zoom, c.rotation, rect(c.viewport), rect(c.bounds),
+					c.shake.strength, c.shake.duration, c.shake.time)
+			}
+			Command::LoadImage { file, id } => write!(f, "load_image {:?} -> {}", file, id),
+			Command::Image { image, x, y, w, h } => write!(f, "image {} {} {} {} {}", image, x, y, w, h),
+			Command::FreeTexture { id } => write!(f, "free_texture {}", id),
+			Command::LoadFont { id } => write!(f, "load_font -> {}", id),
+			Command::FreeFont { id } => write!(f, "free_font {}", id),
+		}
+	}
+}
+
+fn blend_name(mode: BlendMode) -> &'static str {
+	match mode { BlendMode::Alpha => "alpha", BlendMode::Add => "add", BlendMode::Multiply => "multiply" }
+}
+
+fn scaling_name(scaling: Scaling) -> &'static str {
+	match scaling { Scaling::Fit => "fit", Scaling::Integer => "integer", Scaling::Stretch => "stretch" }
+}
+
+pub fn to_text(commands: &[Command]) -> String {
+	let mut ret = String::new();
+	for c in commands { ret += &format!("{}\n", c); }
+	ret
+}
+
+// Reads back what to_text writes. Blank lines and lines starting with # are skipped so snapshots can have comments
+pub fn from_text(text: &str) -> std::result::Result<Vec<Command>, String> {
+	let mut ret = Vec::new();
+	for (n, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') { continue; }
+		ret.push(parse_command(line).map_err(|e| format!("line {}: {} ({})", n + 1, e, line))?);
+	}
+	Ok(ret)
+}
+
+fn parse_command(line: &str) -> std::result::Result<Command, String> {
+	let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+	// Everything that isn't a string is just space separated numbers
+	fn nums<T: std::str::FromStr>(rest: &str, count: usize) -> std::result::Result<Vec<T>, String> {
+		let ret = rest.split_whitespace().map(|n| n.parse::<T>().map_err(|_| format!("bad number {:?}", n))).collect::<std::result::Result<Vec<T>, String>>()?;
+		if ret.len() != count { return Err(format!("expected {} arguments, got {}", count, ret.len())); }
+		Ok(ret)
+	}
+	fn id(rest: &str) -> std::result::Result<u32, String> {
+		let id = rest.trim().strip_prefix("->").ok_or("missing -> id")?;
+		id.trim().parse::<u32>().map_err(|_| format!("bad id {:?}", id))
+	}
+
+	Ok(match name {
+		"setup" => Command::Setup,
+		"draw" => Command::Draw,
+		"destroy" => Command::Destroy,
+		"rect" => { let n = nums::<f32>(rest, 4)?; Command::Rect { x: n[0], y: n[1], w: n[2], h: n[3] } }
+		"layer" => Command::Layer { layer: nums::<i32>(rest, 1)?[0] },
+		"transform" => { let n = nums::<f32>(rest, 6)?; Command::Transform { transform: Transform::new(n[0], n[1], n[2], n[3], n[4], n[5]) } }
+		"push_matrix" => Command::PushMatrix,
+		"pop_matrix" => Command::PopMatrix,
+		"push_style" => Command::PushStyle,
+		"pop_style" => Command::PopStyle,
+		"style" => {
+
+			// style <fill> <stroke> <weight> <blend> <tint>, colors are r,g,b,a or none
+			let args: Vec<&str> = rest.split_whitespace().collect();
+			if args.len() != 5 { return Err(format!("expected 5 arguments, got {}", args.len())); }
+			let color = |s: &str| -> std::result::Result<Option<[f32; 4]>, String> {
+				if s == "none" { return Ok(None); }
+				let c = nums::<f32>(&s.replace(',', " "), 4)?;
+				Ok(Some([c[0], c[1], c[2], c[3]]))
+			};
+			let blend = [BlendMode::Alpha, BlendMode::Add, BlendMode::Multiply].into_iter().find(|b| blend_name(*b) == args[3])
+				.ok_or_else(|| format!("unknown blend mode {:?}", args[3]))?;
+			Command::Style { style: Style { fill: color(args[0])?, stroke: color(args[1])?, stroke_weight: nums::<f32>(args[2], 1)?[0], blend, tint: color(args[4])? } }
+		}
+		"screen" => {
+
+			// screen <w> <h> <scale> <virtual wxh> <scaling> <letterbox>, virtual size is none without one
+			let args: Vec<&str> = rest.split_whitespace().collect();
+			if args.len() != 6 { return Err(format!("expected 6 arguments, got {}", args.len())); }
+			let size = nums::<u32>(&format!("{} {}", args[0], args[1]), 2)?;
+			let virtual_size = match args[3] {
+				"none" => None,
+				v => { let v = nums::<u32>(&v.replace('x', " "), 2)?; Some(Vec2::new(v[0], v[1])) }
+			};
+			let scaling = [Scaling::Fit, Scaling::Integer, Scaling::Stretch].into_iter().find(|s| scaling_name(*s) == args[4])
+				.ok_or_else(|| format!("unknown scaling {:?}", args[4]))?;
+			let c = nums::<f32>(&args[5].replace(',', " "), 4)?;
+			Command::Screen { screen: Screen { size: Vec2::new(size[0], size[1]), scale: nums::<f32>(args[2], 1)?[0], virtual_size, scaling, letterbox: [c[0], c[1], c[2], c[3]] } }
+		}
+		"camera" if rest.trim() == "none" => Command::Camera { camera: None },
+		"camera" => {
+
+			// camera <target x> <target y> <zoom> <rotation> <viewport> <bounds> <shake>, rects are x,y,w,h or none and shake is
+			// strength,duration,time
+			let args: Vec<&str> = rest.split_whitespace().collect();
+			if args.len() != 7 { return Err(format!("expected 7 arguments, got {}", args.len())); }
+			let rect = |s: &str| -> std::result::Result<Option<[f32; 4]>, String> {
+				if s == "none" { return Ok(None); }
+				let r = nums::<f32>(&s.replace(',', " "), 4)?;
+				Ok(Some([r[0], r[1], r[2], r[3]]))
+			};
+			let n = nums::<f32>(&args[..4].join(" "), 4)?;
+			let shake = nums::<f32>(&args[6].replace(',', " "), 3)?;
+			Command::Camera { camera: Some(Camera2D { target: Vec2::new(n[0], n[1]), zoom: n[2], rotation: n[3], viewport: rect(args[4])?, bounds: rect(args[5])?,
+				shake: Shake { strength: shake[0], duration: shake[1], time: shake[2] } }) }
+		}
+		"image" => {
+			let n = nums::<i32>(rest, 5)?;
+			Command::Image { image: u32::try_from(n[0]).map_err(|_| "bad image id")?, x: n[1], y: n[2], w: n[3], h: n[4] }
+		}
+		"free_texture" => Command::FreeTexture { id: nums::<u32>(rest, 1)?[0] },
+		"load_font" => Command::LoadFont { id: id(rest)? },
+		"free_font" => Command::FreeFont { id: nums::<u32>(rest, 1)?[0] },
+		"load_image" => {
+			let (file, rest) = parse_string(rest)?;
+			Command::LoadImage { file, id: id(rest)? }
+		}
+		_ => return Err(format!("unknown command {:?}", name)),
+	})
+}
+
+// Parses a quoted string the way {:?} writes it (only the escapes a path would need), returning whatever's left after it
+fn parse_string(s: &str) -> std::result::Result<(String, &str), String> {
+	let mut chars = s.trim_start().char_indices();
+	let start = s.len() - s.trim_start().len();
+	if chars.next().map(|c| c.1) != Some('"') { return Err("expected a string".into()); }
+	let mut ret = String::new();
+	while let Some((i, c)) = chars.next() {
+		match c {
+			'"' => return Ok((ret, &s[start + i + 1..])),
+			'\\' => match chars.next().map(|c| c.1) {
+				Some('\\') => ret.push('\\'),
+				Some('"') => ret.push('"'),
+				Some('\'') => ret.push('\''),
+				Some('n') => ret.push('\n'),
+				Some('t') => ret.push('\t'),
+				c => return Err(format!("unsupported escape {:?}", c)),
+			},
+			c => ret.push(c),
+		}
+	}
+	Err("unterminated string".into())
+}
+
+// Plays a recording back into another backend. Ids from the recording get mapped onto the handles the backend actually hands out
+pub fn replay(commands: &[Command], g: &mut dyn GraphicsAPI) -> Result<()> {
+	let mut textures = HashMap::<u32, TextureHandle>::new();
+	let mut fonts = HashMap::<u32, FontHandle>::new();
+	let unknown = |id: &u32| RendererError::StaleHandle(format!("recorded id {}", id));
+	for c in commands {
+		match c {
+			Command::Setup => { g.setup()?; }
+			Command::Draw => g.draw()?,
+			Command::Destroy => g.destroy()?,
+			Command::Rect { x, y, w, h } => g.rect(*x, *y, *w, *h)?,
+			Command::Layer { layer } => g.layer(*layer)?,
+			Command::Style { style } => g.set_style(*style)?,
+			Command::PushStyle => g.push_style()?,
+			Command::PopStyle => g.pop_style()?,
+			Command::Transform { transform } => g.set_transform(*transform)?,
+			Command::PushMatrix => g.push_matrix()?,
+			Command::PopMatrix => g.pop_matrix()?,
+			Command::Screen { screen } => g.set_screen(*screen)?,
+			Command::Camera { camera } => g.set_camera(*camera)?,
+			Command::LoadImage { file, id } => { textures.insert(*id, g.load_image(file)?); }
+			Command::Image { image, x, y, w, h } => g.image(*textures.get(image).ok_or_else(|| unknown(image))?, *x, *y, *w, *h)?,
+			Command::FreeTexture { id } => g.free_texture(textures.remove(id).ok_or_else(|| unknown(id))?)?,
+			Command::LoadFont { id } => { fonts.insert(*id, g.load_font()?); }
+			Command::FreeFont { id } => g.free_font(fonts.remove(id).ok_or_else(|| unknown(id))?)?,
+		}
+	}
+	Ok(())
+}
+
+impl GraphicsAPI for RecordContext {
+	fn setup(&mut self) -> Result<&mut dyn GraphicsAPI> {
+		self.commands.push(Command::Setup);
+		if let Some(g) = self.inner.as_mut() { g.setup()?; }
+		Ok(self)
+	}
+
+	fn draw(&mut self) -> Result<()> {
+		self.commands.push(Command::Draw);
+		self.transforms = Stack::default();
+		self.camera = None;
+		if let Some(g) = self.inner.as_mut() { g.draw()?; }
+		Ok(())
+	}
+
+	fn destroy(&mut self) -> Result<()> {
+		self.commands.push(Command::Destroy);
+		if let Some(g) = self.inner.as_mut() { g.destroy()?; }
+		Ok(())
+	}
+
+	fn rect(&mut self, x: f32, y: f32, w: f32, h: f32) -> Result<()> {
+		self.commands.push(Command::Rect { x, y, w, h });
+		if let Some(g) = self.inner.as_mut() { g.rect(x, y, w, h)?; }
+		Ok(())
+	}
+
+	fn layer(&mut self, layer: i32) -> Result<()> {
+		self.commands.push(Command::Layer { layer });
+		if let Some(g) = self.inner.as_mut() { g.layer(layer)?; }
+		Ok(())
+	}
+
+	fn style(&self) -> Style { self.styles.current }
+
+	fn set_style(&mut self, style: Style) -> Result<()> {
+		self.styles.current = style;
+		self.commands.push(Command::Style { style });
+		if let Some(g) = self.inner.as_mut() { g.set_style(style)?; }
+		Ok(())
+	}
+
+	fn push_style(&mut self) -> Result<()> {
+		self.styles.push();
+		self.commands.push(Command::PushStyle);
+		if let Some(g) = self.inner.as_mut() { g.push_style()?; }
+		Ok(())
+	}
+
+	fn pop_style(&mut self) -> Result<()> {
+		self.styles.pop("pop_style")?;
+		self.commands.push(Command::PopStyle);
+		if let Some(g) = self.inner.as_mut() { g.pop_style()?; }
+		Ok(())
+	}
+
+	fn transform(&self) -> Transform { self.transforms.current }
+
+	fn set_transform(&mut self, transform: Transform) -> Result<()> {
+		self.transforms.current = transform;
+		self.commands.push(Command::Transform { transform });
+		if let Some(g) = self.inner.as_mut() { g.set_transform(transform)?; }
+		Ok(())
+	}
+
+	fn push_matrix(&mut self) -> Result<()> {
+		self.transforms.push();
+		self.commands.push(Command::PushMatrix);
+		if let Some(g) = self.inner.as_mut() { g.push_matrix()?; }
+		Ok(())
+	}
+
+	fn pop_matrix(&mut self) -> Result<()> {
+		self.transforms.pop("pop_matrix")?;
+		self.commands.push(Command::PopMatrix);
+		if let Some(g) = self.inner.as_mut() { g.pop_matrix()?; }
+		Ok(())
+	}
+
+	fn camera(&self) -> Option<Camera2D> { self.camera }
+
+	fn set_camera(&mut self, camera: Option<Camera2D>) -> Result<()> {
+		self.camera = camera;
+		self.commands.push(Command::Camera { camera });
+		if let Some(g) = self.inner.as_mut() { g.set_camera(camera)?; }
+		Ok(())
+	}
+
+	fn screen(&self) -> Screen { self.screen }
+
+	fn set_screen(&mut self, screen: Screen) -> Result<()> {
+		self.screen = screen;
+		self.commands.push(Command::Screen { screen });
+		if let Some(g) = self.inner.as_mut() { g.set_screen(screen)?; }
+		Ok(())
+	}
+
+	fn load_image(&mut self, file: &str) -> Result<TextureHandle> {
+		let handle = match self.inner.as_mut() {
+			Some(g) => g.load_image(file)?,
+			None => self.own_textures.insert(()),
+		};
+		let id = self.next_id();
+		self.textures.insert(handle, id);
+		self.commands.push(Command::LoadImage { file: file.to_string(), id });
+		Ok(handle)
+	}
+
+	fn image(&mut self, image: TextureHandle, x: i32, y: i32, w: i32, h: i32) -> Result<()> {
+		let id = *self.textures.get(&image).ok_or_else(|| image.stale())?;
+		self.commands.push(Command::Image { image: id, x, y, w, h });
+		if let Some(g) = self.inner.as_mut() { g.image(image, x, y, w, h)?; }
+		Ok(())
+	}
+
+	fn free_texture(&mut self, texture: TextureHandle) -> Result<()> {
+		let id = self.textures.remove(&texture).ok_or_else(|| texture.stale())?;
+		self.commands.push(Command::FreeTexture { id });
+		match self.inner.as_mut() {
+			Some(g) => g.free_texture(texture),
+			None => self.own_textures.remove(texture),
+		}
+	}
+
+	fn load_font(&mut self) -> Result<FontHandle> {
+		let handle = match self.inner.as_mut() {
+			Some(g) => g.load_font()?,
+			None => self.own_fonts.insert(()),
+		};
+		let id = self.next_id();
+		self.fonts.insert(handle, id);
+		self.commands.push(Command::LoadFont { id });
+		Ok(handle)
+	}
+
+	fn free_font(&mut self, font: FontHandle) -> Result<()> {
+		let id = self.fonts.remove(&font).ok_or_else(|| font.stale())?;
+		self.commands.push(Command::FreeFont { id });
+		match self.inner.as_mut() {
+			Some(g) => g.free_font(font),
+			None => self.own_fonts.remove(font),
+		}
+	}
+}
+
+#[test]
+fn record_snapshot() {
+	let mut g = RecordContext::new();
+	g.setup().unwrap();
+	let img = g.load_image("./res/bg.jpg").unwrap();
+	g.rect(0.0, 0.0, 0.5, 0.5).unwrap();
+	g.image(img, 10, 20, 30, 40).unwrap();
+	g.draw().unwrap();
+	g.rect(-1.0, -1.0, 0.25, 0.1).unwrap();
+	g.free_texture(img).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.frames().len(), 2);
+	assert!(g.image(img, 0, 0, 1, 1).is_err());
+
+	let snapshot = include_str!("../../res/snapshots/record.txt");
+	assert_eq!(g.to_text(), snapshot);
+	assert_eq!(from_text(snapshot).unwrap(), g.commands);
+}
+
+#[test]
+fn record_replay() {
+	use crate::graphics::soft::SoftContext;
+	use crate::graphics::tex::{Tex, Channels};
+
+	// Same frame drawn straight into one backend and replayed into another has to come out the same
+	let mut direct = SoftContext::new(32, 32);
+	let mut g = RecordContext::wrap(Box::new(SoftContext::new(32, 32)));
+	let mut replayed = SoftContext::new(32, 32);
+	replayed.texture(Tex::new(1, 1, Channels::RGB));
+	for g in [&mut direct as &mut dyn GraphicsAPI, &mut g] {
+		let img = g.load_image("./res/bg.jpg").unwrap();
+		g.image(img, 0, 0, 16, 16).unwrap();
+		g.rect(-0.5, -0.5, 0.25, 0.75).unwrap();
+		g.draw().unwrap();
+	}
+	replay(&g.commands, &mut replayed).unwrap();
+	assert_eq!(direct.frame().data, replayed.frame().data);
+}
+
+#[test]
+fn record_style() {
+	let mut g = RecordContext::new();
+	g.push_style().unwrap();
+	g.stroke([0.0, 0.5, 1.0, 1.0]).unwrap();
+	g.blend_mode(BlendMode::Multiply).unwrap();
+	g.pop_style().unwrap();
+	assert!(g.pop_style().is_err());
+	assert_eq!(g.style(), Style::default());
+	assert_eq!(g.commands[1].to_string(), "style 1.0,0.0,0.0,1.0 0.0,0.5,1.0,1.0 1.0 alpha none");
+	assert_eq!(from_text(&g.to_text()).unwrap(), g.commands);
+}
+
+#[test]
+fn record_transform() {
+	let mut g = RecordContext::new();
+	g.push_matrix().unwrap();
+	g.translate(0.5, -0.25).unwrap();
+	g.pop_matrix().unwrap();
+	g.scale(2.0, 3.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.transform(), Transform::IDENTITY);
+	assert_eq!(g.commands[1].to_string(), "transform 1.0 0.0 0.0 1.0 0.5 -0.25");
+	assert_eq!(from_text(&g.to_text()).unwrap(), g.commands);
+}
+
+#[test]
+fn record_screen() {
+	use crate::graphics::api::Scaling;
+	let mut g = RecordContext::wrap(Box::new(crate::graphics::soft::SoftContext::new(32, 16)));
+	g.virtual_resolution(16, 16, Scaling::Integer).unwrap();
+	g.resize(64, 32, 2.0).unwrap();
+	assert_eq!(g.screen().viewport(), [16, 0, 32, 32]);
+	assert_eq!(g.commands[0].to_string(), "screen 32 16 1.0 16x16 integer 0.0,0.0,0.0,1.0");
+	assert_eq!(from_text(&g.to_text()).unwrap(), g.commands);
+}
+
+#[test]
+fn record_camera() {
+	let mut g = RecordContext::new();
+	g.set_camera(Some(Camera2D { zoom: 2.0, bounds: Some([0.0, 0.0, 640.0, 480.0]), ..Camera2D::new(Vec2::new(10.0, 20.0)) })).unwrap();
+	g.set_camera(None).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.camera(), None);
+	assert_eq!(g.commands[0].to_string(), "camera 10.0 20.0 2.0 0.0 none 0.0,0.0,640.0,480.0 0.0,0.0,0.0");
+	assert_eq!(from_text(&g.to_text()).unwrap(), g.commands);
+}
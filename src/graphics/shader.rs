@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use crate::graphics::error::{RendererError, Result, ShaderDiagnostic, ShaderStage};
+
+// Where #include "file" looks
+pub const INCLUDE_DIR: &str = "res";
+
+// What gets #defined at the top of every stage of a variant. Sorted, so the same set always makes the same cache key
+pub type Defines = BTreeMap<String, String>;
+pub fn defines(pairs: &[(&str, &str)]) -> Defines {
+	pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+// Which line of which file (an index into ShaderSource::files) a line of the assembled source came from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Origin {
+	pub file: usize,
+	pub line: usize,
+}
+
+// One stage ready to hand to the driver, with where each of its lines came from. None is a line we made up, like the defines
+pub struct StageSource {
+	pub stage: ShaderStage,
+	pub source: String,
+	pub origins: Vec<Option<Origin>>,
+}
+
+// What a line of the file starts, if it's one of the section markers
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Section { Shared, Stage(ShaderStage) }
+
+// A shader file with its includes pulled in, split up into sections:
+//
+//   #version 330 core        <- anything before the first marker is shared by every stage
+//   # vert
+//   #include "common.glsl"   <- relative to res/
+//   ...
+//   # geom                   <- optional
+//   # frag
+//   # shared                 <- more shared lines, added to every stage like the ones at the top
+//
+// Files without a # vert marker are the old format, where everything above # frag is the vertex shader
+pub struct ShaderSource {
+	pub path: String,
+
+	// Every file that went into it (the first one being path), which is what hot reloading watches
+	pub files: Vec<String>,
+	texts: Vec<Vec<String>>,
+	shared: Vec<Origin>,
+	stages: Vec<(ShaderStage, Vec<Origin>)>,
+}
+
+impl ShaderSource {
+	pub fn parse(path: &str, text: &str) -> Result<Self> {
+		ShaderSource::parse_in(path, text, Path::new(INCLUDE_DIR))
+	}
+
+	pub fn parse_in(path: &str, text: &str, include_dir: &Path) -> Result<Self> {
+		let mut src = ShaderSource { path: path.to_string(), files: vec![path.to_string()], texts: vec![text.lines().map(String::from).collect()],
+			shared: Vec::new(), stages: Vec::new() };
+		let lines = src.expand(0, include_dir, &mut vec![0])?;
+
+		let legacy = !lines.iter().any(|l| src.section(l) == Some(Section::Stage(ShaderStage::Vertex)));
+		let mut current = Section::Shared;
+		if legacy { src.stages.push((ShaderStage::Vertex, Vec::new())); current = Section::Stage(ShaderStage::Vertex); }
+		for l in lines {
+			match src.section(&l) {
+				Some(Section::Stage(stage)) => {
+					if src.stages.iter().any(|s| s.0 == stage) { return Err(src.error(l, format!("there's already a {:?} stage", stage))); }
+					src.stages.push((stage, Vec::new()));
+					current = Section::Stage(stage);
+				}
+				Some(Section::Shared) => current = Section::Shared,
+
+				// Sections can't be picked back up once another one starts, so the stage being filled is always the last one
+				None if current == Section::Shared => src.shared.push(l),
+				None => src.stages.last_mut().unwrap().1.push(l),
+			}
+		}
+
+		for (stage, marker) in [(ShaderStage::Vertex, "# vert"), (ShaderStage::Fragment, "# frag")] {
+			if !src.stages.iter().any(|s| s.0 == stage) {
+				return Err(src.error(Origin { file: 0, line: 1 }, format!("missing the \"{}\" line that starts the {:?} shader", marker, stage)));
+			}
+		}
+		Ok(src)
+	}
+
+	fn text(&self, l: &Origin) -> &str {
+		self.texts[l.file].get(l.line - 1).map_or("", |s| s.as_str())
+	}
+
+	fn section(&self, l: &Origin) -> Option<Section> {
+		match self.text(l).trim() {
+			"# shared" => Some(Section::Shared),
+			"# vert" => Some(Section::Stage(ShaderStage::Vertex)),
+			"# geom" => Some(Section::Stage(ShaderStage::Geometry)),
+			"# frag" => Some(Section::Stage(ShaderStage::Fragment)),
+			_ => None,
+		}
+	}
+
+	fn error(&self, l: Origin, message: String) -> RendererError {
+		RendererError::ShaderSyntax(ShaderDiagnostic { file: self.files[l.file].clone(), line: l.line, col: None, message, source: self.text(&l).to_string() })
+	}
+
+	// Every line of a file with its includes spliced in, stack being the files we're in the middle of so a cycle can be caught
+	fn expand(&mut self, file: usize, include_dir: &Path, stack: &mut Vec<usize>) -> Result<Vec<Origin>> {
+		let mut ret = Vec::new();
+		for line in 1..=self.texts[file].len() {
+			let l = Origin { file, line };
+			let Some(rest) = self.text(&l).trim().strip_prefix("#include") else { ret.push(l); continue; };
+			let Some(name) = rest.trim().strip_prefix('"').and_then(|r| r.strip_suffix('"')).map(String::from) else {
+				return Err(self.error(l, "expected #include \"file\"".into()));
+			};
+
+			let path = include_dir.join(&name).to_string_lossy().into_owned();
+			let index = match self.files.iter().position(|f| *f == path) {
+				Some(i) => i,
+				None => {
+					let text = std::fs::read_to_string(&path).map_err(|e| self.error(l, format!("couldn't include {:?}: {}", path, e)))?;
+					self.files.push(path);
+					self.texts.push(text.lines().map(String::from).collect());
+					self.files.len() - 1
+				}
+			};
+			if stack.contains(&index) { return Err(self.error(l, format!("{:?} ends up including itself", name))); }
+			stack.push(index);
+			ret.extend(self.expand(index, include_dir, stack)?);
+			stack.pop();
+		}
+		Ok(ret)
+	}
+
+	pub fn stages(&self) -> impl Iterator<Item = ShaderStage> + '_ {
+		self.stages.iter().map(|s| s.0)
+	}
+
+	// Puts a stage together: #version first (the stage's own if it has one), then the defines, the shared lines and the stage itself
+	pub fn stage(&self, stage: ShaderStage, defines: &Defines) -> Option<StageSource> {
+		let body = &self.stages.iter().find(|s| s.0 == stage)?.1;
+		let is_version = |l: &&Origin| self.text(l).trim_start().starts_with("#version");
+		let version = body.iter().find(is_version).or_else(|| self.shared.iter().find(is_version));
+
+		let mut ret = StageSource { stage, source: String::new(), origins: Vec::new() };
+		if let Some(l) = version {
+			ret.source += &format!("{}\n", self.text(l));
+			ret.origins.push(Some(*l));
+		}
+		for (name, value) in defines {
+			ret.source += &format!("#define {} {}\n", name, value);
+			ret.origins.push(None);
+		}
+		for l in self.shared.iter().chain(body).filter(|l| !is_version(l)) {
+			ret.source += &format!("{}\n", self.text(l));
+			ret.origins.push(Some(*l));
+		}
+		Some(ret)
+	}
+
+	// Points a line (counting from 1) of an assembled stage back at the file it came from. Lines that aren't in any file get blamed on the stage
+	pub fn locate(&self, stage: &StageSource, line: usize, col: Option<usize>, message: String) -> ShaderDiagnostic {
+		match stage.origins.get(line.max(1) - 1).copied().flatten() {
+			Some(o) => ShaderDiagnostic { file: self.files[o.file].clone(), line: o.line, col, message, source: self.text(&o).to_string() },
+			None => ShaderDiagnostic { file: format!("<{:?} stage of {}>", stage.stage, self.path), line, col, message,
+				source: stage.source.lines().nth(line.max(1) - 1).unwrap_or("").to_string() },
+		}
+	}
+
+	// Turns a driver's compile log for one of the stages into messages about lines of the files. Lines of the log that
+	// don't start a new message (some drivers wrap, or add notes) get tacked onto the one before
+	pub fn diagnostics(&self, stage: &StageSource, log: &str) -> Vec<ShaderDiagnostic> {
+		let mut ret = Vec::<ShaderDiagnostic>::new();
+		for l in log.lines().map(str::trim).filter(|l| !l.is_empty()) {
+			match parse_log_line(l) {
+				Some(LogLine { line, col, message }) => ret.push(self.locate(stage, line, col, message)),
+				None => if let Some(d) = ret.last_mut() { d.message += &format!("\n{}", l); },
+			}
+		}
+		ret
+	}
+}
+
+// Keeps an eye on the files a shader came from by polling their modification times, so saving one while the game is running can reload it
+pub struct ShaderWatcher {
+	pub path: String,
+	files: Vec<(PathBuf, Option<SystemTime>)>,
+
+	// Stat-ing every file every frame would be a bit much
+	pub interval: Duration,
+	last_poll: Option<Instant>,
+}
+
+impl ShaderWatcher {
+	pub fn new(path: &str) -> Self {
+		let mut ret = ShaderWatcher { path: path.to_string(), files: Vec::new(), interval: Duration::from_millis(250), last_poll: None };
+		ret.watch(&[path.to_string()]);
+		ret
+	}
+
+	// Swaps out the files being watched, e.g. when a reload picked up a new include
+	pub fn watch(&mut self, files: &[String]) {
+		self.files = files.iter().map(|f| (PathBuf::from(f), modified(f))).collect();
+	}
+
+	// Whether anything changed since the last time this said so
+	pub fn changed(&mut self) -> bool {
+		if self.last_poll.is_some_and(|t| t.elapsed() < self.interval) { return false; }
+		self.last_poll = Some(Instant::now());
+		let mut ret = false;
+		for (file, time) in self.files.iter_mut() {
+			let now = modified(file);
+			if now != *time { *time = now; ret = true; }
+		}
+		ret
+	}
+}
+
+fn modified(path: impl AsRef<std::path::Path>) -> Option<SystemTime> {
+	std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Where in the stage a log line says the problem is, lines (and columns when there are any) count from 1
+#[derive(Debug, PartialEq, Eq)]
+pub struct LogLine {
+	pub line: usize,
+	pub col: Option<usize>,
+	pub message: String,
+}
+
+// Every driver has its own idea of how to write these:
+//   Mesa     `0:12(3): error: syntax error, unexpected ...`
+//   NVIDIA   `0(12) : error C0000: syntax error, unexpected ...`
+//   AMD etc  `ERROR: 0:12: 'nope' : undeclared identifier`
+// The leading number is the source string, and there's only ever one of those
+pub fn parse_log_line(line: &str) -> Option<LogLine> {
+	fn number(s: &str) -> Option<(usize, &str)> {
+		let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+		Some((s[..end].parse().ok()?, &s[end..]))
+	}
+	let line = line.trim();
+
+	for (prefix, severity) in [("ERROR: ", "error"), ("WARNING: ", "warning")] {
+		if let Some(rest) = line.strip_prefix(prefix) {
+			let (_, rest) = number(rest)?;
+			let (l, rest) = number(rest.strip_prefix(':')?)?;
+			let message = format!("{}: {}", severity, rest.strip_prefix(':')?.trim());
+			return Some(LogLine { line: l, col: None, message });
+		}
+	}
+
+	let (_, rest) = number(line)?;
+	if let Some(rest) = rest.strip_prefix(':') {
+		let (l, rest) = number(rest)?;
+		let (c, rest) = number(rest.strip_prefix('(')?)?;
+		let message = rest.strip_prefix("):")?.trim().to_string();
+
+		// Mesa's columns start at 0
+		return Some(LogLine { line: l, col: Some(c + 1), message });
+	}
+	let (l, rest) = number(rest.strip_prefix('(')?)?;
+	let message = rest.strip_prefix(')')?.trim_start().strip_prefix(':')?.trim().to_string();
+	Some(LogLine { line: l, col: None, message })
+}
+
+#[test]
+fn shader_logs() {
+	assert_eq!(parse_log_line("0:12(3): error: syntax error, unexpected '}'"),
+		Some(LogLine { line: 12, col: Some(4), message: "error: syntax error, unexpected '}'".into() }));
+	assert_eq!(parse_log_line("0(12) : error C0000: syntax error, unexpected '}'"),
+		Some(LogLine { line: 12, col: None, message: "error C0000: syntax error, unexpected '}'".into() }));
+	assert_eq!(parse_log_line("ERROR: 0:12: 'nope' : undeclared identifier"),
+		Some(LogLine { line: 12, col: None, message: "error: 'nope' : undeclared identifier".into() }));
+	assert_eq!(parse_log_line("ERROR: 1 compilation errors.  No code generated."), None);
+
+	// Fragment line 3 is line 6 of the file, and the note after the error sticks to it
+	let src = ShaderSource::parse("test.glsl", "#version 330 core\nvoid main() {}\n# frag\n#version 330 core\nvoid main() {\n\tnope;\n}\n").unwrap();
+	let frag = src.stage(ShaderStage::Fragment, &Defines::new()).unwrap();
+	let d = src.diagnostics(&frag, "0:3(1): error: `nope' undeclared\nsome note\n");
+	assert_eq!(d.len(), 1);
+	assert_eq!(d[0].to_string(), "test.glsl:6:2: error: `nope' undeclared\nsome note\n    | \tnope;\n    | \t^");
+	assert_eq!(src.stage(ShaderStage::Vertex, &Defines::new()).unwrap().source, "#version 330 core\nvoid main() {}\n");
+}
+
+#[test]
+fn shader_sections() {
+	let dir = Path::new("./target/shader_sections");
+	std::fs::create_dir_all(dir).unwrap();
+	std::fs::write(dir.join("common.glsl"), "#include \"consts.glsl\"\nvec4 tint(vec4 c) { return c * TINT; }\n").unwrap();
+	std::fs::write(dir.join("consts.glsl"), "const float TINT = 0.5;\n").unwrap();
+	std::fs::write(dir.join("loop.glsl"), "#include \"loop.glsl\"\n").unwrap();
+
+	let text = "#version 330 core\n# vert\nvoid main() {}\n# geom\nvoid main() {}\n# shared\n#include \"common.glsl\"\n# frag\nvoid main() {}\n";
+	let src = ShaderSource::parse_in("test.glsl", text, dir).unwrap();
+	assert_eq!(src.stages().collect::<Vec<_>>(), [ShaderStage::Vertex, ShaderStage::Geometry, ShaderStage::Fragment]);
+	assert_eq!(src.files.len(), 3);
+
+	// Version first, then the defines, then everything shared (wherever it was in the file), then the stage
+	let frag = src.stage(ShaderStage::Fragment, &defines(&[("B", "2"), ("A", "")])).unwrap();
+	assert_eq!(frag.source, "#version 330 core\n#define A \n#define B 2\nconst float TINT = 0.5;\nvec4 tint(vec4 c) { return c * TINT; }\nvoid main() {}\n");
+
+	// Errors in included files point into them, and made up lines point at the stage
+	let d = src.diagnostics(&frag, "0:4(0): error: one\n0:2(0): error: two\n");
+	assert_eq!((d[0].file.as_str(), d[0].line), ("./target/shader_sections/consts.glsl", 1));
+	assert_eq!((d[1].file.as_str(), d[1].line, d[1].source.as_str()), ("<Fragment stage of test.glsl>", 2, "#define A "));
+
+	let err = |text: &str| match ShaderSource::parse_in("test.glsl", text, dir) { Err(RendererError::ShaderSyntax(d)) => (d.line, d.message), _ => panic!() };
+	assert_eq!(err("# vert\n#include \"loop.glsl\"\n# frag\n").1, "\"loop.glsl\" ends up including itself");
+	assert_eq!(err("# vert\n\n#include \"gone.glsl\"\n# frag\n").0, 3);
+	assert_eq!(err("# vert\n# frag\n# vert\n"), (3, "there's already a Vertex stage".into()));
+	assert_eq!(err("void main() {}\n").1, "missing the \"# frag\" line that starts the Fragment shader");
+}
+
+#[test]
+fn shader_watcher() {
+	use std::fs::File;
+	let dir = std::path::Path::new("./target/shader_watcher");
+	std::fs::create_dir_all(dir).unwrap();
+	let file = dir.join("watched.glsl");
+	std::fs::write(&file, "a").unwrap();
+	let touch = |secs: u64| File::options().write(true).open(&file).unwrap().set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+	touch(1);
+
+	let mut w = ShaderWatcher::new(file.to_str().unwrap());
+	w.interval = Duration::ZERO;
+	assert!(!w.changed());
+	touch(2);
+	assert!(w.changed());
+	assert!(!w.changed());
+
+	// A file that's gone (editors like to delete and rewrite) counts as a change too
+	std::fs::remove_file(&file).unwrap();
+	assert!(w.changed());
+}
@@ -0,0 +1,414 @@
+use crate::graphics::api::{BlendMode, GraphicsAPI, Screen, Stack, Style, Transform, Vec2};
+use std::collections::HashSet;
+use crate::graphics::batch::{Batch, Blend, Instance, Key, ShapeData, View};
+use crate::graphics::camera::Camera2D;
+use crate::graphics::tex::{Channels, FontAtlas, LoadedFont, Tex};
+use crate::graphics::error::Result;
+use crate::graphics::handle::{Pool, Texture, Font, TextureHandle, FontHandle};
+
+// Pure CPU batch renderer. Rasterizes the exact vertex/index data the GL renderer would upload into a Tex, so frames can be rendered (and checked) without a GPU
+pub struct SoftContext {
+	pub fb: Tex,
+	pub batch: Batch,
+	pub textures: Pool<Texture, Tex>,
+	pub fonts: Pool<Font, LoadedFont>,
+
+	// Textures with any alpha in them, which get drawn after the opaque stuff on their layer
+	translucent: HashSet<TextureHandle>,
+	pub layer: i32,
+	pub styles: Stack<Style>,
+	pub clear_color: [f32; 4],
+
+	// The framebuffer is the physical size, set scale to draw as if on a HiDPI display
+	pub screen: Screen,
+}
+
+impl SoftContext {
+	pub fn new(w: usize, h: usize) -> Self {
+		SoftContext {
+			fb: Tex::new(w, h, Channels::RGBA),
+			batch: Batch::default(),
+			textures: Pool::new(),
+			fonts: Pool::new(),
+			translucent: HashSet::new(), layer: 0, styles: Stack::default(),
+			clear_color: [0.0; 4], screen: Screen::new(w as u32, h as u32, 1.0) }
+	}
+
+	pub fn frame(&self) -> &Tex { &self.fb }
+
+	pub fn texture(&mut self, tex: Tex) -> TextureHandle {
+		let opaque = tex.opaque();
+		let handle = self.textures.insert(tex);
+		if !opaque { self.translucent.insert(handle); }
+		handle
+	}
+
+	fn submit(&mut self, start: usize, blend: Blend, texture: Option<TextureHandle>) {
+		self.batch.submit(Key { view: self.batch.view, layer: self.layer, blend, mode: self.styles.current.blend, material: None, instanced: false, texture }, 0, start);
+	}
+
+	// Same as the GL clear, everything goes to the clear color and the letterbox bars to theirs
+	pub fn clear(&mut self) {
+		let px = self.clear_color.map(to_unorm);
+		for y in 0..self.fb.h {
+			for x in 0..self.fb.w { self.fb.set_texel(x, y, px); }
+		}
+		let px = self.screen.letterbox.map(to_unorm);
+		for [x, y, w, h] in self.screen.bars() {
+			for y in y..y + h {
+				for x in x..x + w { self.fb.set_texel(x as usize, y as usize, px); }
+			}
+		}
+	}
+
+	fn triangle(fb: &mut Tex, tex: Option<&Tex>, view: &View, mode: BlendMode, v: [&ShapeData; 3]) {
+
+		// Logical pixels to clip space the way the vertex shader does it, then to framebuffer pixels the way the GL viewport does (y flips
+		// back, since row 0 is the top of the Tex)
+		let [vx, vy, vw, vh] = view.viewport.map(|v| v as f32);
+		let p = v.map(|v| view.projection.apply(v.pos)).map(|p| Vec2::new(vx + (p.x + 1.0) * 0.5 * vw, vy + (1.0 - p.y) * 0.5 * vh));
+
+		// Keeps every triangle wound the same way so the edge tests below don't need to care
+		let area = edge(&p[0], &p[1], &p[2]);
+		if area == 0.0 { return; }
+		let (p, v) = if area < 0.0 { ([p[0], p[2], p[1]], [v[0], v[2], v[1]]) } else { (p, v) };
+		let area = area.abs();
+
+		// Only walks the pixels the triangle could cover, and nothing outside the viewport (GL clips there too)
+		let minx = p.iter().map(|p| p.x).fold(f32::MAX, f32::min).floor().max(vx) as usize;
+		let miny = p.iter().map(|p| p.y).fold(f32::MAX, f32::min).floor().max(vy) as usize;
+		let maxx = (p.iter().map(|p| p.x).fold(f32::MIN, f32::max).ceil().max(0.0) as usize).min((vx + vw) as usize).min(fb.w);
+		let maxy = (p.iter().map(|p| p.y).fold(f32::MIN, f32::max).ceil().max(0.0) as usize).min((vy + vh) as usize).min(fb.h);
+
+		let edges = [(p[1], p[2]), (p[2], p[0]), (p[0], p[1])];
+		for y in miny..maxy {
+			for x in minx..maxx {
+				let c = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+				let mut l = [0.0; 3];
+				let mut inside = true;
+				for (i, (a, b)) in edges.iter().enumerate() {
+					l[i] = edge(a, b, &c);
+
+					// Top-left fill rule, so triangles sharing an edge don't blend the same pixel twice
+					if l[i] < 0.0 || (l[i] == 0.0 && !top_left(a, b)) { inside = false; break; }
+				}
+				if !inside { continue; }
+				let l = l.map(|l| l / area);
+
+				// Interpolates everything the vertex shader passes along
+				let uv = Vec2::new(
+					l[0] * v[0].tex.x + l[1] * v[1].tex.x + l[2] * v[2].tex.x,
+					l[0] * v[0].tex.y + l[1] * v[1].tex.y + l[2] * v[2].tex.y);
+				let mut col = [0.0; 4];
+				for (i, c) in col.iter_mut().enumerate() {
+					*c = l[0] * v[0].col[i] + l[1] * v[1].col[i] + l[2] * v[2].col[i];
+				}
+
+				// Fragment shader: color = vec4(mix(s.xyz, v_col.xyz, v_col.a), s.a)
+				let s = sample(tex, uv);
+				let src = [mix(s[0], col[0], col[3]), mix(s[1], col[1], col[3]), mix(s[2], col[2], col[3]), s[3]];
+				let dst = fb.texel(x, y).map(|c| c as f32 / 255.0);
+				fb.set_texel(x, y, blend(mode, src, dst).map(to_unorm));
+			}
+		}
+	}
+}
+
+fn edge(a: &Vec2<f32>, b: &Vec2<f32>, p: &Vec2<f32>) -> f32 {
+	(b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+// With y going down and the winding fixed above, top edges go right and left edges go up
+fn top_left(a: &Vec2<f32>, b: &Vec2<f32>) -> bool {
+	(a.y == b.y && b.x > a.x) || b.y < a.y
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 { a * (1.0 - t) + b * t }
+
+fn to_unorm(c: f32) -> u8 { (c.clamp(0.0, 1.0) * 255.0).round() as u8 }
+
+// The glBlendFunc each mode sets. Alpha is (GL_SRC_ALPHA, GL_ONE_MINUS_SRC_COLOR), the alpha channel uses the alpha of the source color
+// for both. Add is (GL_SRC_ALPHA, GL_ONE) and Multiply is (GL_DST_COLOR, GL_ZERO)
+fn blend(mode: BlendMode, src: [f32; 4], dst: [f32; 4]) -> [f32; 4] {
+	match mode {
+		BlendMode::Alpha => [src[0] * src[3] + dst[0] * (1.0 - src[0]),
+			src[1] * src[3] + dst[1] * (1.0 - src[1]),
+			src[2] * src[3] + dst[2] * (1.0 - src[2]),
+			src[3] * src[3] + dst[3] * (1.0 - src[3])],
+		BlendMode::Add => [0, 1, 2, 3].map(|i| src[i] * src[3] + dst[i]),
+		BlendMode::Multiply => [0, 1, 2, 3].map(|i| src[i] * dst[i]),
+	}
+}
+
+// GL_LINEAR filtering with GL_CLAMP_TO_EDGE wrapping, which is what GLContext::texture sets up. Sampling no texture is white, like the NO_TEXTURE slot in the shader
+fn sample(tex: Option<&Tex>, uv: Vec2<f32>) -> [f32; 4] {
+	let Some(t) = tex else { return [1.0; 4]; };
+	let x = uv.x * t.w as f32 - 0.5;
+	let y = uv.y * t.h as f32 - 0.5;
+	let (fx, fy) = (x - x.floor(), y - y.floor());
+	let clampx = |i: f32| (i.max(0.0) as usize).min(t.w - 1);
+	let clampy = |i: f32| (i.max(0.0) as usize).min(t.h - 1);
+	let (x0, x1, y0, y1) = (clampx(x.floor()), clampx(x.floor() + 1.0), clampy(y.floor()), clampy(y.floor() + 1.0));
+	let (a, b, c, d) = (t.texel(x0, y0), t.texel(x1, y0), t.texel(x0, y1), t.texel(x1, y1));
+
+	let mut ret = [0.0; 4];
+	for (i, r) in ret.iter_mut().enumerate() {
+		let top = mix(a[i] as f32, b[i] as f32, fx);
+		let bottom = mix(c[i] as f32, d[i] as f32, fx);
+		*r = mix(top, bottom, fy) / 255.0;
+	}
+	ret
+}
+
+impl GraphicsAPI for SoftContext {
+	fn setup(&mut self) -> Result<&mut dyn GraphicsAPI> {
+		Ok(self)
+	}
+
+	fn draw(&mut self) -> Result<()> {
+		self.layer = 0;
+		let views = self.batch.end_frame(&self.screen);
+		if self.batch.is_empty() { return Ok(()); }
+		self.clear();
+
+		// Rasterizes each submission with the texture it samples, in the same order GL draws them
+		self.batch.sort();
+		for s in self.batch.submissions.iter() {
+
+			// Freeing a texture that's still queued is caught here rather than sampling whatever took its slot
+			let tex = match s.key.texture { Some(t) => Some(self.textures.get(t)?), None => None };
+			for tri in self.batch.indexdata[s.start..s.end].chunks_exact(3) {
+				let v = [tri[0], tri[1], tri[2]].map(|i| &self.batch.shapedata[i as usize]);
+				SoftContext::triangle(&mut self.fb, tex, &views[s.key.view], s.key.mode, v);
+			}
+		}
+		self.batch.clear();
+		Ok(())
+	}
+
+	fn destroy(&mut self) -> Result<()> {
+		self.textures.drain();
+		self.fonts.drain();
+		self.translucent.clear();
+		Ok(())
+	}
+
+	fn rect(&mut self, x: f32, y: f32, w: f32, h: f32) -> Result<()> {
+		for ([x, y, w, h], color) in self.styles.current.rect(x, y, w, h) {
+			let start = self.batch.indexdata.len();
+			self.batch.rect(x, y, w, h, color);
+			self.submit(start, self.styles.current.blend(color), None);
+		}
+		Ok(())
+	}
+
+	fn layer(&mut self, layer: i32) -> Result<()> {
+		self.layer = layer;
+		Ok(())
+	}
+
+	fn style(&self) -> Style { self.styles.current }
+	fn set_style(&mut self, style: Style) -> Result<()> {
+		self.styles.current = style;
+		Ok(())
+	}
+	fn push_style(&mut self) -> Result<()> {
+		self.styles.push();
+		Ok(())
+	}
+	fn pop_style(&mut self) -> Result<()> { self.styles.pop("pop_style") }
+
+	fn transform(&self) -> Transform { self.batch.transforms.current }
+	fn set_transform(&mut self, transform: Transform) -> Result<()> {
+		self.batch.transforms.current = transform;
+		Ok(())
+	}
+	fn push_matrix(&mut self) -> Result<()> {
+		self.batch.transforms.push();
+		Ok(())
+	}
+	fn pop_matrix(&mut self) -> Result<()> { self.batch.transforms.pop("pop_matrix") }
+
+	fn camera(&self) -> Option<Camera2D> { self.batch.camera() }
+	fn set_camera(&mut self, camera: Option<Camera2D>) -> Result<()> {
+		self.batch.set_camera(camera);
+		Ok(())
+	}
+
+	fn screen(&self) -> Screen { self.screen }
+	fn set_screen(&mut self, screen: Screen) -> Result<()> {
+		if screen.size != self.screen.size { self.fb = Tex::new(screen.size.x as usize, screen.size.y as usize, Channels::RGBA); }
+		self.screen = screen;
+		Ok(())
+	}
+
+	fn load_image(&mut self, file: &str) -> Result<TextureHandle> {
+		Ok(self.texture(Tex::load(file)?))
+	}
+
+	fn image(&mut self, image: TextureHandle, x: i32, y: i32, w: i32, h: i32) -> Result<()> {
+		self.textures.get(image)?;
+
+		// Color alpha is how much the vertex color covers the texture, so images only get any of it when tinted. Submissions get drawn one
+		// texture at a time here, so it's always slot 0
+		let instance = Instance::new(Vec2::new(x as f32, y as f32), Vec2::new(w as f32, h as f32), self.styles.current.image_color());
+		let start = self.batch.indexdata.len();
+		self.batch.push_quad(instance.vertices().map(|v| ShapeData { slot: 0, ..v }));
+		self.submit(start, self.styles.current.image_blend(self.translucent.contains(&image)), Some(image));
+		Ok(())
+	}
+
+	fn free_texture(&mut self, texture: TextureHandle) -> Result<()> {
+		self.textures.remove(texture)?;
+		self.translucent.remove(&texture);
+		Ok(())
+	}
+
+	fn load_font(&mut self) -> Result<FontHandle> {
+		let mut atlas = FontAtlas::new();
+		atlas.load("default", "./res/font.ttf")?;
+		let texture = self.texture(atlas.tex.clone());
+		Ok(self.fonts.insert(LoadedFont { atlas, texture }))
+	}
+
+	fn free_font(&mut self, font: FontHandle) -> Result<()> {
+		let font = self.fonts.remove(font)?;
+		self.free_texture(font.texture)
+	}
+}
+
+#[test]
+fn soft_rect() {
+	let mut g = SoftContext::new(64, 64);
+	g.rect(32.0, 16.0, 16.0, 16.0).unwrap();
+	g.draw().unwrap();
+
+	// Top right quadrant of the screen, up to halfway to the edges
+	assert_eq!(g.frame().texel(40, 20), [255, 0, 0, 255]);
+	assert_eq!(g.frame().texel(20, 20), [0, 0, 0, 0]);
+	assert_eq!(g.frame().texel(40, 40), [0, 0, 0, 0]);
+
+	// Both triangles share the diagonal, so it has to come out the same as the rest of the rect
+	assert_eq!(g.frame().texel(40, 24), [255, 0, 0, 255]);
+}
+
+#[test]
+fn soft_image() {
+	let mut g = SoftContext::new(64, 64);
+	let mut tex = Tex::new(2, 2, Channels::RGB);
+	tex.set_texel(0, 0, [255, 0, 0, 255]);
+	tex.set_texel(1, 0, [0, 255, 0, 255]);
+	tex.set_texel(0, 1, [0, 0, 255, 255]);
+	tex.set_texel(1, 1, [255, 255, 255, 255]);
+	let id = g.texture(tex);
+	g.image(id, 32, 32, 32, 32).unwrap();
+	g.draw().unwrap();
+
+	// Pixels from the top left, so the quad lands on exactly the bottom right quarter
+	assert_eq!(g.frame().texel(32, 32), [255, 0, 0, 255]);
+	assert_eq!(g.frame().texel(63, 32), [0, 255, 0, 255]);
+	assert_eq!(g.frame().texel(32, 63), [0, 0, 255, 255]);
+	assert_eq!(g.frame().texel(63, 63), [255, 255, 255, 255]);
+	assert_eq!(g.frame().texel(8, 8), [0, 0, 0, 0]);
+}
+
+#[test]
+fn soft_free_texture() {
+	use crate::graphics::error::RendererError;
+	let mut g = SoftContext::new(8, 8);
+	let old = g.texture(Tex::new(1, 1, Channels::RGB));
+	g.free_texture(old).unwrap();
+
+	// The new texture reuses the slot, the old handle still has to be turned away
+	let new = g.texture(Tex::new(1, 1, Channels::RGB));
+	assert!(matches!(g.image(old, 0, 0, 4, 4), Err(RendererError::StaleHandle(_))));
+	assert!(matches!(g.free_texture(old), Err(RendererError::StaleHandle(_))));
+	g.image(new, 0, 0, 4, 4).unwrap();
+
+	// Freed while still queued up for the next draw
+	g.free_texture(new).unwrap();
+	assert!(matches!(g.draw(), Err(RendererError::StaleHandle(_))));
+}
+
+#[test]
+fn soft_style() {
+	use crate::graphics::error::RendererError;
+	let mut g = SoftContext::new(64, 64);
+
+	// An outline only, a pixel either side of the edge
+	g.push_style().unwrap();
+	g.no_fill().unwrap();
+	g.stroke([1.0, 1.0, 1.0, 1.0]).unwrap();
+	g.stroke_weight(2.0).unwrap();
+	g.rect(16.0, 16.0, 32.0, 32.0).unwrap();
+	g.pop_style().unwrap();
+	assert!(matches!(g.pop_style(), Err(RendererError::StackUnderflow(_))));
+
+	// Back to the red fill, added onto the green already there
+	g.fill([0.0, 1.0, 0.0, 1.0]).unwrap();
+	g.rect(0.0, 0.0, 16.0, 16.0).unwrap();
+	g.layer(1).unwrap();
+	g.fill([1.0, 0.0, 0.0, 1.0]).unwrap();
+	g.blend_mode(BlendMode::Add).unwrap();
+	g.rect(0.0, 0.0, 16.0, 16.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.frame().texel(16, 16), [255, 255, 255, 255]);
+	assert_eq!(g.frame().texel(32, 32), [0, 0, 0, 0]);
+	assert_eq!(g.frame().texel(4, 4), [255, 255, 0, 255]);
+
+	// Tint covers the image by its alpha
+	let mut tex = Tex::new(1, 1, Channels::RGB);
+	tex.set_texel(0, 0, [0, 0, 255, 255]);
+	let tex = g.texture(tex);
+	g.blend_mode(BlendMode::Alpha).unwrap();
+	g.tint([1.0, 0.0, 0.0, 0.4]).unwrap();
+	g.image(tex, 32, 16, 16, 16).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.frame().texel(40, 24), [102, 0, 153, 255]);
+}
+
+#[test]
+fn soft_hidpi() {
+	let mut g = SoftContext::new(64, 64);
+	g.screen = Screen::new(64, 64, 2.0);
+
+	// Twice as many pixels as there are logical ones, so a rect lands at double its coords
+	g.rect(16.0, 8.0, 8.0, 8.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!((g.frame().texel(40, 20), g.frame().texel(20, 20)), ([255, 0, 0, 255], [0, 0, 0, 0]));
+	assert_eq!(g.screen.logical_size(), Vec2::new(32.0, 32.0));
+	assert_eq!(g.screen.to_physical(Vec2::new(16.0, 8.0)), Vec2::new(32.0, 16.0));
+
+	// The top left corner is the top left of clip space, and back
+	assert_eq!(g.screen.to_clip(Vec2::new(0.0, 0.0)), Vec2::new(-1.0, 1.0));
+	assert_eq!(g.screen.from_clip(Vec2::new(0.5, -0.5)), Vec2::new(24.0, 24.0));
+}
+
+#[test]
+fn soft_letterbox() {
+	use crate::graphics::api::Scaling;
+	let mut g = SoftContext::new(64, 48);
+	g.virtual_resolution(32, 32, Scaling::Fit).unwrap();
+	g.letterbox([0.0, 0.0, 1.0, 1.0]).unwrap();
+
+	// 1.5x fits, leaving bars either side
+	assert_eq!(g.screen.viewport(), [8, 0, 48, 48]);
+	g.rect(0.0, 0.0, 32.0, 32.0).unwrap();
+	g.rect(-8.0, 0.0, 8.0, 8.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!([g.frame().texel(8, 0), g.frame().texel(55, 47), g.frame().texel(4, 4), g.frame().texel(60, 24)],
+		[[255, 0, 0, 255], [255, 0, 0, 255], [0, 0, 255, 255], [0, 0, 255, 255]]);
+
+	// Whole steps only, and the mouse comes back into virtual pixels, then however it fits
+	g.virtual_resolution(32, 32, Scaling::Integer).unwrap();
+	assert_eq!(g.screen.viewport(), [16, 8, 32, 32]);
+	assert_eq!(g.screen.to_logical(Vec2::new(20.0, 10.0)), Vec2::new(4.0, 2.0));
+	g.virtual_resolution(32, 32, Scaling::Stretch).unwrap();
+	assert_eq!(g.screen.to_physical(Vec2::new(16.0, 16.0)), Vec2::new(32.0, 24.0));
+
+	// Resizing takes the framebuffer with it
+	g.resize(16, 16, 1.0).unwrap();
+	g.rect(0.0, 0.0, 16.0, 16.0).unwrap();
+	g.draw().unwrap();
+	assert_eq!((g.frame().w, g.frame().h, g.frame().texel(15, 15)), (16, 16, [0, 0, 0, 0]));
+}
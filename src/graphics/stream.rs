@@ -0,0 +1,193 @@
+use std::time::{Duration, Instant};
+use glow::HasContext;
+use crate::graphics::error::{RendererError, Result};
+
+// Frames in flight a persistent buffer has room for, so the GPU can still be reading the last two while the next gets written
+pub const REGIONS: usize = 3;
+
+// Nothing gets allocated smaller than this, and shrinking waits this many frames of using under a quarter of the space
+pub const MIN_CAPACITY: usize = 64 * 1024;
+pub const SHRINK_AFTER: usize = 300;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StreamMode {
+	// One buffer REGIONS times the capacity, mapped for good (ARB_buffer_storage). Each frame writes the next region, waiting on its
+	// fence if the GPU hasn't got through it yet
+	Persistent,
+
+	// Reallocates the storage every frame before uploading, which lets the driver hand back fresh memory instead of stalling
+	Orphan,
+}
+
+impl StreamMode {
+	// Persistent mapping if the driver can do it
+	pub fn detect(gl: &glow::Context) -> Self {
+		let v = gl.version();
+		if ((v.major, v.minor) >= (4, 4) && !v.is_embedded) || gl.supported_extensions().contains("GL_ARB_buffer_storage") {
+			StreamMode::Persistent
+		} else {
+			StreamMode::Orphan
+		}
+	}
+}
+
+// How streaming has been going, for finding out why a frame was slow
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct StreamStats {
+	pub frames: usize,
+	pub bytes: usize,
+	pub total_bytes: usize,
+	pub capacity: usize,
+	pub reallocations: usize,
+
+	// Times a region was still being read by the GPU when it came back around, and how long was spent waiting for it
+	pub waits: usize,
+	pub wait_time: Duration,
+}
+
+// Where an upload landed. Moving to a new buffer means whatever pointed at the old one (the vertex layout) has to be set up again
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Upload {
+	pub offset: usize,
+	pub moved: bool,
+}
+
+// A buffer that gets completely rewritten every frame
+pub struct StreamBuffer {
+	pub target: u32,
+	pub mode: StreamMode,
+	pub buffer: Option<glow::Buffer>,
+	pub stats: StreamStats,
+	pub shrink_after: usize,
+
+	// Offsets have to be a multiple of this (the vertex size, so base vertex lands on one)
+	align: usize,
+	map: *mut u8,
+	region: usize,
+	fences: [Option<glow::Fence>; REGIONS],
+
+	// Most used since the capacity last changed, and for how many frames
+	peak: usize,
+	since: usize,
+}
+
+impl StreamBuffer {
+	pub fn new(target: u32, align: usize, mode: StreamMode) -> Self {
+		StreamBuffer { target, mode, buffer: None, stats: StreamStats::default(), shrink_after: SHRINK_AFTER,
+			align: align.max(1), map: std::ptr::null_mut(), region: 0, fences: [None; REGIONS], peak: 0, since: 0 }
+	}
+
+	// Room for `len` bytes, a power of two so growing doesn't happen every frame
+	fn size(&self, len: usize) -> usize {
+		len.max(MIN_CAPACITY).next_power_of_two().div_ceil(self.align) * self.align
+	}
+
+	// Grows as soon as something doesn't fit, and shrinks once a while has gone by using much less than there is
+	fn capacity(&mut self, len: usize) -> Option<usize> {
+		self.peak = self.peak.max(len);
+		self.since += 1;
+		let capacity = self.stats.capacity;
+		if self.buffer.is_none() || len > capacity { return Some(self.size(len)); }
+		if self.since >= self.shrink_after {
+			let peak = self.peak;
+			(self.peak, self.since) = (0, 0);
+			if peak * 4 < capacity && capacity > MIN_CAPACITY { return Some(self.size(peak * 2)); }
+		}
+		None
+	}
+
+	unsafe fn allocate(&mut self, gl: &glow::Context, capacity: usize) -> Result<bool> {
+		let moved = self.buffer.is_none() || self.mode == StreamMode::Persistent;
+		if moved {
+			self.release(gl);
+			self.buffer = Some(gl.create_buffer().map_err(|e| RendererError::ResourceCreation(format!("stream buffer ({})", e)))?);
+			gl.bind_buffer(self.target, self.buffer);
+		}
+		if self.mode == StreamMode::Persistent {
+			let size = (capacity * REGIONS) as i32;
+			let flags = glow::MAP_WRITE_BIT | glow::MAP_PERSISTENT_BIT | glow::MAP_COHERENT_BIT;
+			gl.buffer_storage(self.target, size, None, flags);
+			self.map = gl.map_buffer_range(self.target, 0, size, flags);
+			if self.map.is_null() { return Err(RendererError::ResourceCreation("stream buffer (couldn't map it)".into())); }
+		}
+		self.stats.capacity = capacity;
+		self.stats.reallocations += 1;
+		(self.peak, self.since) = (0, 0);
+		Ok(moved)
+	}
+
+	// Puts this frame's data in, leaving the buffer bound
+	pub fn upload(&mut self, gl: &glow::Context, data: &[u8]) -> Result<Upload> {
+		unsafe {
+			gl.bind_buffer(self.target, self.buffer);
+			let moved = match self.capacity(data.len()) {
+				Some(capacity) => self.allocate(gl, capacity)?,
+				None => false,
+			};
+			self.stats.frames += 1;
+			self.stats.bytes = data.len();
+			self.stats.total_bytes += data.len();
+
+			let offset = match self.mode {
+				StreamMode::Persistent => {
+					self.region = (self.region + 1) % REGIONS;
+					self.wait(gl);
+					let offset = self.region * self.stats.capacity;
+					std::ptr::copy_nonoverlapping(data.as_ptr(), self.map.add(offset), data.len());
+					offset
+				}
+				StreamMode::Orphan => {
+					gl.buffer_data_size(self.target, self.stats.capacity as i32, glow::STREAM_DRAW);
+					gl.buffer_sub_data_u8_slice(self.target, 0, data);
+					0
+				}
+			};
+			Ok(Upload { offset, moved })
+		}
+	}
+
+	// Blocks until the GPU is done with the region about to be written
+	unsafe fn wait(&mut self, gl: &glow::Context) {
+		let Some(fence) = self.fences[self.region].take() else { return; };
+		if gl.client_wait_sync(fence, 0, 0) == glow::TIMEOUT_EXPIRED {
+			let start = Instant::now();
+			self.stats.waits += 1;
+			while gl.client_wait_sync(fence, glow::SYNC_FLUSH_COMMANDS_BIT, 100_000_000) == glow::TIMEOUT_EXPIRED {}
+			self.stats.wait_time += start.elapsed();
+		}
+		gl.delete_sync(fence);
+	}
+
+	// Goes after the draws that read this frame's region, only persistent buffers need it
+	pub fn fence(&mut self, gl: &glow::Context) {
+		if self.mode != StreamMode::Persistent || self.buffer.is_none() { return; }
+		unsafe {
+			if let Some(old) = self.fences[self.region].take() { gl.delete_sync(old); }
+			self.fences[self.region] = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).ok();
+		}
+	}
+
+	unsafe fn release(&mut self, gl: &glow::Context) {
+		for fence in self.fences.iter_mut() {
+			if let Some(f) = fence.take() { gl.delete_sync(f); }
+		}
+		if let Some(buffer) = self.buffer.take() {
+			if !self.map.is_null() {
+				gl.bind_buffer(self.target, Some(buffer));
+				gl.unmap_buffer(self.target);
+				self.map = std::ptr::null_mut();
+			}
+			gl.delete_buffer(buffer);
+		}
+	}
+
+	pub fn destroy(&mut self, gl: &glow::Context) {
+		unsafe { self.release(gl); }
+		self.stats.capacity = 0;
+	}
+}
+
+// What's in a slice, as bytes to upload
+pub fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+	unsafe { core::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
@@ -0,0 +1,287 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+use crate::graphics::api::{BlendMode, GraphicsAPI, Screen, Stack, Style, Transform, Vec2};
+use crate::graphics::batch::{Batch, View};
+use crate::graphics::camera::Camera2D;
+use crate::graphics::tex::{FontAtlas, LoadedFont, Tex};
+use crate::graphics::error::Result;
+use crate::graphics::handle::{Pool, Texture, Font, TextureHandle, FontHandle};
+
+// Loaded image, already encoded the way it goes into the document
+pub struct SvgImage {
+	pub w: u32,
+	pub h: u32,
+	pub uri: String,
+}
+
+// Something queued for the frame, kept in order so images and shapes overlap the way they were submitted
+enum Item {
+	Shapes(usize, usize /* indices */, BlendMode),
+	Image { image: TextureHandle, pos: Vec2<f32>, size: Vec2<f32>, tint: Option<[f32; 4]>, mode: BlendMode, transform: Transform },
+}
+
+// Vector screenshots: every draw() turns what was queued into an SVG document
+pub struct SvgContext {
+
+	// The document is the physical size
+	pub screen: Screen,
+	pub batch: Batch,
+	pub images: Pool<Texture, SvgImage>,
+	pub fonts: Pool<Font, LoadedFont>,
+
+	// Sorted by view and layer (and nothing else, there's no batching to gain here) when drawn
+	items: Vec<(usize, i32, Item)>,
+	pub layer: i32,
+	pub styles: Stack<Style>,
+
+	// The last frame drawn, and how many have been
+	pub svg: String,
+	pub frame: usize,
+
+	// If set every frame also gets written out as frame_N.svg in here
+	pub out_dir: Option<PathBuf>,
+}
+
+impl SvgContext {
+	pub fn new(w: u32, h: u32) -> Self {
+		SvgContext { screen: Screen::new(w, h, 1.0), batch: Batch::default(), images: Pool::new(), fonts: Pool::new(), items: Vec::new(), layer: 0, styles: Stack::default(),
+			svg: String::new(), frame: 0, out_dir: None }
+	}
+
+	pub fn texture(&mut self, tex: &Tex) -> Result<TextureHandle> {
+		let mut png = Vec::<u8>::new();
+		tex.to_image().write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
+		Ok(self.images.insert(SvgImage { w: tex.w as u32, h: tex.h as u32, uri: format!("data:image/png;base64,{}", base64(&png)) }))
+	}
+
+	fn shapes(&self, out: &mut String, start: usize, end: usize, mode: BlendMode, view: &View) {
+
+		// Runs of triangles with the same color become a single path. Vertex alpha is how much of the color goes over the (white) texel
+		// rather than see through-ness, so the fill is that mix and opaque like on the other backends
+		let mut d = String::new();
+		let mut col: Option<[f32; 4]> = None;
+		let flush = |out: &mut String, d: &mut String, col: Option<[f32; 4]>| {
+			if let Some(c) = col {
+				let c = c.map(|v| 1.0 + (v - 1.0) * c[3]);
+				*out += &format!("<path d=\"{}\" fill=\"{}\"{}/>\n", d.trim_end(), rgb(c), blend_style(mode));
+			}
+			d.clear();
+		};
+		for tri in self.batch.indexdata[start..end].chunks_exact(3) {
+			let v = [tri[0], tri[1], tri[2]].map(|i| &self.batch.shapedata[i as usize]);
+			if col != Some(v[0].col) { flush(out, &mut d, col); col = Some(v[0].col); }
+			let p = v.map(|v| view.physical.apply(v.pos));
+			d += &format!("M{} {} L{} {} L{} {} Z ", p[0].x, p[0].y, p[1].x, p[1].y, p[2].x, p[2].y);
+		}
+		flush(out, &mut d, col);
+	}
+}
+
+// CSS blend modes are the closest there is, alpha is just how SVG draws anyway
+fn blend_style(mode: BlendMode) -> &'static str {
+	match mode {
+		BlendMode::Alpha => "",
+		BlendMode::Add => " style=\"mix-blend-mode:plus-lighter\"",
+		BlendMode::Multiply => " style=\"mix-blend-mode:multiply\"",
+	}
+}
+
+fn rgb(c: [f32; 4]) -> String {
+	let c = c.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+	format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2])
+}
+
+// Standard base64 with padding, all the data uris need
+fn base64(data: &[u8]) -> String {
+	const CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut ret = String::with_capacity(data.len().div_ceil(3) * 4);
+	for chunk in data.chunks(3) {
+		let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+		for i in 0..4 {
+			if i <= chunk.len() { ret.push(CHARS[(n >> (18 - 6 * i) & 63) as usize] as char); } else { ret.push('='); }
+		}
+	}
+	ret
+}
+
+impl GraphicsAPI for SvgContext {
+	fn setup(&mut self) -> Result<&mut dyn GraphicsAPI> {
+		Ok(self)
+	}
+
+	fn draw(&mut self) -> Result<()> {
+		self.layer = 0;
+		let views = self.batch.end_frame(&self.screen);
+
+		// Every draw writes a document, even with nothing in it, so the files line up with the frames
+		let mut out = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n", self.screen.size.x, self.screen.size.y);
+		self.items.sort_by_key(|i| (i.0, i.1));
+
+		// Views that don't cover the whole document get their own group, clipped to their viewport
+		let full = [0, 0, self.screen.size.x as i32, self.screen.size.y as i32];
+		let mut group = None;
+		for (v, _, item) in self.items.iter() {
+			let view = &views[*v];
+			if group != Some(*v) {
+				if group.is_some_and(|g| views[g].viewport != full) { out += "</g>\n"; }
+				group = Some(*v);
+				if view.viewport != full {
+					let [x, y, w, h] = view.viewport;
+					out += &format!("<clipPath id=\"view{0}\"><rect x=\"{1}\" y=\"{2}\" width=\"{3}\" height=\"{4}\"/></clipPath>\n<g clip-path=\"url(#view{0})\">\n", v, x, y, w, h);
+				}
+			}
+			match item {
+				Item::Shapes(start, end, mode) => self.shapes(&mut out, *start, *end, *mode, view),
+				Item::Image { image, pos, size, tint, mode, transform } => {
+					let transform = view.physical * transform;
+					let [[a, c, e], [b, d, f], _] = transform.m;
+					let t = if transform == Transform::IDENTITY { String::new() } else { format!(" transform=\"matrix({} {} {} {} {} {})\"", a, b, c, d, e, f) };
+					out += &format!("<image x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\" href=\"{}\"{}{}/>\n",
+						pos.x, pos.y, size.x, size.y, self.images.get(*image)?.uri, t, blend_style(*mode));
+
+					// A tint covers the image by its alpha, which is what a rect of it on top does too (except over see through texels)
+					if let Some(c) = tint {
+						out += &format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"{}\"{}/>\n",
+							pos.x, pos.y, size.x, size.y, rgb(*c), c[3], t);
+					}
+				}
+			}
+		}
+		if group.is_some_and(|g| views[g].viewport != full) { out += "</g>\n"; }
+
+		// Bars go on last, over anything that strayed outside the viewport
+		for [x, y, w, h] in self.screen.bars() {
+			let c = self.screen.letterbox;
+			out += &format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"{}\"/>\n", x, y, w, h, rgb(c), c[3]);
+		}
+		out += "</svg>\n";
+
+		if let Some(dir) = &self.out_dir {
+			std::fs::write(dir.join(format!("frame_{}.svg", self.frame)), &out)?;
+		}
+		self.svg = out;
+		self.frame += 1;
+		self.batch.clear();
+		self.items.clear();
+		Ok(())
+	}
+
+	fn destroy(&mut self) -> Result<()> {
+		self.images.drain();
+		self.fonts.drain();
+		Ok(())
+	}
+
+	fn rect(&mut self, x: f32, y: f32, w: f32, h: f32) -> Result<()> {
+		let (start, mode) = (self.batch.indexdata.len(), self.styles.current.blend);
+		for ([x, y, w, h], color) in self.styles.current.rect(x, y, w, h) { self.batch.rect(x, y, w, h, color); }
+		match self.items.last_mut() {
+			Some((view, layer, Item::Shapes(_, end, m))) if *view == self.batch.view && *layer == self.layer && *m == mode => *end = self.batch.indexdata.len(),
+			_ => self.items.push((self.batch.view, self.layer, Item::Shapes(start, self.batch.indexdata.len(), mode))),
+		}
+		Ok(())
+	}
+
+	fn layer(&mut self, layer: i32) -> Result<()> {
+		self.layer = layer;
+		Ok(())
+	}
+
+	fn style(&self) -> Style { self.styles.current }
+	fn set_style(&mut self, style: Style) -> Result<()> {
+		self.styles.current = style;
+		Ok(())
+	}
+	fn push_style(&mut self) -> Result<()> {
+		self.styles.push();
+		Ok(())
+	}
+	fn pop_style(&mut self) -> Result<()> { self.styles.pop("pop_style") }
+
+	fn transform(&self) -> Transform { self.batch.transforms.current }
+	fn set_transform(&mut self, transform: Transform) -> Result<()> {
+		self.batch.transforms.current = transform;
+		Ok(())
+	}
+	fn push_matrix(&mut self) -> Result<()> {
+		self.batch.transforms.push();
+		Ok(())
+	}
+	fn pop_matrix(&mut self) -> Result<()> { self.batch.transforms.pop("pop_matrix") }
+
+	fn camera(&self) -> Option<Camera2D> { self.batch.camera() }
+	fn set_camera(&mut self, camera: Option<Camera2D>) -> Result<()> {
+		self.batch.set_camera(camera);
+		Ok(())
+	}
+
+	fn screen(&self) -> Screen { self.screen }
+	fn set_screen(&mut self, screen: Screen) -> Result<()> {
+		self.screen = screen;
+		Ok(())
+	}
+
+	fn load_image(&mut self, file: &str) -> Result<TextureHandle> {
+		self.texture(&Tex::load(file)?)
+	}
+
+	fn image(&mut self, image: TextureHandle, x: i32, y: i32, w: i32, h: i32) -> Result<()> {
+		self.images.get(image)?;
+
+		// Stays where it was drawn, the transform (and the view's, when it's drawn) takes it the rest of the way to document pixels
+		self.items.push((self.batch.view, self.layer, Item::Image { image, pos: Vec2::new(x as f32, y as f32), size: Vec2::new(w as f32, h as f32),
+			tint: self.styles.current.tint, mode: self.styles.current.blend, transform: self.batch.transforms.current }));
+		Ok(())
+	}
+
+	fn free_texture(&mut self, texture: TextureHandle) -> Result<()> {
+		self.images.remove(texture)?;
+		Ok(())
+	}
+
+	fn load_font(&mut self) -> Result<FontHandle> {
+		let mut atlas = FontAtlas::new();
+		atlas.load("default", "./res/font.ttf")?;
+		let texture = self.texture(&atlas.tex)?;
+		Ok(self.fonts.insert(LoadedFont { atlas, texture }))
+	}
+
+	fn free_font(&mut self, font: FontHandle) -> Result<()> {
+		let font = self.fonts.remove(font)?;
+		self.free_texture(font.texture)
+	}
+}
+
+#[test]
+fn svg_frame() {
+	let mut g = SvgContext::new(64, 32);
+	g.rect(32.0, 8.0, 16.0, 8.0).unwrap();
+	let img = g.load_image("./res/bg.jpg").unwrap();
+	g.image(img, 32, 12, 8, 4).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.frame, 1);
+	assert!(g.svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"64\" height=\"32\""));
+
+	// Both triangles of the rect end up in one red path, in the same place it'd be on screen
+	assert!(g.svg.contains("<path d=\"M32 8 L48 8 L32 16 Z M32 16 L48 8 L48 16 Z\" fill=\"#ff0000\"/>"));
+	assert!(g.svg.contains("<image x=\"32\" y=\"12\" width=\"8\" height=\"4\" preserveAspectRatio=\"none\" href=\"data:image/png;base64,iVBORw0KGgo"));
+	assert_eq!(base64(b"tetris!"), "dGV0cmlzIQ==");
+
+	// Half alpha is half way to white, same as GL and soft draw it
+	g.set_style(Style { fill: Some([1.0, 0.0, 0.0, 0.5]), ..g.style() }).unwrap();
+	g.rect(0.0, 0.0, 8.0, 8.0).unwrap();
+	g.draw().unwrap();
+	assert!(g.svg.contains("<path d=\"M0 0 L8 0 L0 8 Z M0 8 L8 0 L8 8 Z\" fill=\"#ff8080\"/>"), "{}", g.svg);
+
+	// A camera's view gets clipped to its viewport
+	g.set_camera(Some(Camera2D { viewport: Some([0.0, 0.0, 32.0, 32.0]), ..Camera2D::default() })).unwrap();
+	g.rect(-4.0, -4.0, 8.0, 8.0).unwrap();
+	g.draw().unwrap();
+	assert!(g.svg.contains("<clipPath id=\"view1\"><rect x=\"0\" y=\"0\" width=\"32\" height=\"32\"/></clipPath>\n<g clip-path=\"url(#view1)\">\n<path d=\"M12 12 L20 12"));
+	assert!(g.svg.contains("</g>\n</svg>"));
+
+	// Nothing drawn is still a frame
+	g.draw().unwrap();
+	assert_eq!(g.frame, 4);
+	assert_eq!(g.svg, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"64\" height=\"32\" viewBox=\"0 0 64 32\">\n</svg>\n");
+}
@@ -0,0 +1,164 @@
+use std::io::Write;
+use std::time::Duration;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{cursor, terminal, execute};
+use crate::graphics::api::{GraphicsAPI, Screen, Style, Transform};
+use crate::graphics::camera::Camera2D;
+use crate::graphics::soft::SoftContext;
+use crate::graphics::error::Result;
+use crate::graphics::handle::{TextureHandle, FontHandle};
+
+// Draws frames into a terminal with half block characters and 24 bit colors, two pixels per character cell.
+// Everything is rasterized by the software renderer at the terminal's resolution first
+pub struct TermContext {
+	pub soft: SoftContext,
+	pub out: Box<dyn Write>,
+
+	// Whether we own the terminal (raw mode, alternate screen), only for the real one
+	pub raw: bool,
+	cleared: bool,
+}
+
+impl TermContext {
+	pub fn new(cols: usize, rows: usize, out: Box<dyn Write>) -> Self {
+		TermContext { soft: SoftContext::new(cols, rows * 2), out, raw: false, cleared: false }
+	}
+
+	// Fills the whole terminal, leaving the last line alone so printing it doesn't scroll
+	pub fn terminal() -> Self {
+		let (cols, rows) = terminal::size().unwrap_or((80, 24));
+		let mut ret = TermContext::new(cols as usize, rows.saturating_sub(1).max(1) as usize, Box::new(std::io::stdout()));
+		ret.raw = true;
+		ret
+	}
+
+	// Keys pressed since the last call, doesn't block. Picks up the terminal changing size on the way
+	pub fn keys(&mut self) -> Vec<KeyCode> {
+		let mut ret = Vec::new();
+		while event::poll(Duration::ZERO).unwrap_or(false) {
+			match event::read() {
+				Ok(Event::Key(k)) if k.kind == KeyEventKind::Press => ret.push(k.code),
+				Ok(Event::Resize(cols, rows)) => { let _ = self.resize(cols as u32, rows.saturating_sub(1).max(1) as u32 * 2, 1.0); }
+				_ => (),
+			}
+		}
+		ret
+	}
+
+	// Writes the frame out, each cell's foreground is the top pixel and background the bottom one
+	pub fn present(&mut self) -> std::io::Result<()> {
+		let fb = self.soft.frame();
+		let mut s = String::with_capacity(fb.w * fb.h * 20);
+
+		// Redraws over the last frame instead of scrolling
+		if !self.cleared { s += "\x1b[2J"; self.cleared = true; }
+		s += "\x1b[H";
+
+		// Alpha is against the black the terminal clears to. An odd height leaves the last row's bottom half past the frame, black too
+		let px = |x: usize, y: usize| {
+			if y >= fb.h { return [0; 3]; }
+			let p = fb.texel(x, y);
+			[p[0], p[1], p[2]].map(|c| (c as u32 * p[3] as u32 / 255) as u8)
+		};
+		for y in (0..fb.h).step_by(2) {
+			for x in 0..fb.w {
+				let (t, b) = (px(x, y), px(x, y + 1));
+				s += &format!("\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}", t[0], t[1], t[2], b[0], b[1], b[2]);
+			}
+			s += "\x1b[0m\r\n";
+		}
+		self.out.write_all(s.as_bytes())?;
+		self.out.flush()
+	}
+}
+
+impl GraphicsAPI for TermContext {
+	fn setup(&mut self) -> Result<&mut dyn GraphicsAPI> {
+		if self.raw {
+			terminal::enable_raw_mode()?;
+			execute!(self.out, terminal::EnterAlternateScreen, cursor::Hide)?;
+		}
+		self.soft.setup()?;
+		Ok(self)
+	}
+
+	fn draw(&mut self) -> Result<()> {
+		// An empty frame still goes through soft so the transforms, cameras and layer get reset, there's just nothing new to print
+		let empty = self.soft.batch.is_empty();
+		self.soft.draw()?;
+		if empty { return Ok(()); }
+		Ok(self.present()?)
+	}
+
+	fn destroy(&mut self) -> Result<()> {
+		self.soft.destroy()?;
+		if self.raw {
+			execute!(self.out, cursor::Show, terminal::LeaveAlternateScreen)?;
+			terminal::disable_raw_mode()?;
+		}
+		Ok(())
+	}
+
+	fn rect(&mut self, x: f32, y: f32, w: f32, h: f32) -> Result<()> { self.soft.rect(x, y, w, h) }
+	fn layer(&mut self, layer: i32) -> Result<()> { self.soft.layer(layer) }
+	fn style(&self) -> Style { self.soft.style() }
+	fn set_style(&mut self, style: Style) -> Result<()> { self.soft.set_style(style) }
+	fn push_style(&mut self) -> Result<()> { self.soft.push_style() }
+	fn pop_style(&mut self) -> Result<()> { self.soft.pop_style() }
+	fn transform(&self) -> Transform { self.soft.transform() }
+	fn set_transform(&mut self, transform: Transform) -> Result<()> { self.soft.set_transform(transform) }
+	fn push_matrix(&mut self) -> Result<()> { self.soft.push_matrix() }
+	fn pop_matrix(&mut self) -> Result<()> { self.soft.pop_matrix() }
+	fn camera(&self) -> Option<Camera2D> { self.soft.camera() }
+	fn set_camera(&mut self, camera: Option<Camera2D>) -> Result<()> { self.soft.set_camera(camera) }
+	fn screen(&self) -> Screen { self.soft.screen() }
+	fn set_screen(&mut self, screen: Screen) -> Result<()> {
+
+		// What's on the terminal won't line up with the new size
+		if screen.size != self.soft.screen.size { self.cleared = false; }
+		self.soft.set_screen(screen)
+	}
+	fn load_image(&mut self, file: &str) -> Result<TextureHandle> { self.soft.load_image(file) }
+	fn image(&mut self, image: TextureHandle, x: i32, y: i32, w: i32, h: i32) -> Result<()> { self.soft.image(image, x, y, w, h) }
+	fn free_texture(&mut self, texture: TextureHandle) -> Result<()> { self.soft.free_texture(texture) }
+	fn load_font(&mut self) -> Result<FontHandle> { self.soft.load_font() }
+	fn free_font(&mut self, font: FontHandle) -> Result<()> { self.soft.free_font(font) }
+}
+
+#[test]
+fn term_frame() {
+	use std::sync::{Arc, Mutex};
+
+	// Lets the test look at what got written after handing the writer over
+	#[derive(Clone, Default)]
+	struct Shared(Arc<Mutex<Vec<u8>>>);
+	impl Write for Shared {
+		fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.0.lock().unwrap().write(buf) }
+		fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+	}
+
+	let buf = Shared::default();
+	let mut g = TermContext::new(4, 2, Box::new(buf.clone()));
+	g.setup().unwrap();
+	g.rect(2.0, 0.0, 2.0, 2.0).unwrap();
+	g.draw().unwrap();
+	g.set_transform(Transform::translation(1.0, 0.0)).unwrap();
+	g.set_camera(Some(Camera2D::default())).unwrap();
+	g.draw().unwrap();
+	assert_eq!(g.transform(), Transform::IDENTITY);
+	assert_eq!(g.camera(), None);
+	let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+	let red = "\x1b[38;2;255;0;0m\x1b[48;2;255;0;0m\u{2580}";
+	let black = "\x1b[38;2;0;0;0m\x1b[48;2;0;0;0m\u{2580}";
+
+	// Only the top right quarter is red, and the second (empty) draw doesn't print anything but still starts the next frame fresh
+	assert_eq!(out, format!("\x1b[2J\x1b[H{0}{0}{1}{1}\x1b[0m\r\n{0}{0}{0}{0}\x1b[0m\r\n", black, red));
+
+	// An odd height gets a last row with nothing under it
+	buf.0.lock().unwrap().clear();
+	g.resize(1, 3, 1.0).unwrap();
+	g.rect(0.0, 0.0, 1.0, 3.0).unwrap();
+	g.draw().unwrap();
+	let half = "\x1b[38;2;255;0;0m\x1b[48;2;0;0;0m\u{2580}";
+	assert_eq!(String::from_utf8(buf.0.lock().unwrap().clone()).unwrap(), format!("\x1b[2J\x1b[H{}\x1b[0m\r\n{}\x1b[0m\r\n", red, half));
+}
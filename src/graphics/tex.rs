@@ -2,10 +2,13 @@ use fontdue::*;
 use std::collections::HashMap;
 use std::fs::*;
 use crate::graphics::api::Vec2;
+use crate::graphics::error::{RendererError, Result};
+use crate::graphics::handle::TextureHandle;
 
 // ----- Texture datatypes ------
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Channels { RGB = 3, RGBA = 4, GRAYSCALE = 1 }
+#[derive(Clone)]
 pub struct Tex {
 	pub w: usize,
 	pub h: usize,
@@ -14,30 +17,83 @@ pub struct Tex {
 }
 impl Tex {
 	pub fn new(w: usize, h: usize, channels: Channels) -> Self {
-		Tex { w, h, data: vec![0 as u8; w * h * channels as usize], channels }
+		Tex { w, h, data: vec![0_u8; w * h * channels as usize], channels }
 	}
+
+	// Loads an image file, keeping whatever channels it came with as long as we can upload them
+	pub fn load(file: &str) -> Result<Self> {
+		Tex::from_image(image::io::Reader::open(file)?.decode()?)
+	}
+	pub fn from_image(img: image::DynamicImage) -> Result<Self> {
+		let (w, h) = (img.width() as usize, img.height() as usize);
+		match img {
+			image::DynamicImage::ImageRgb8(img) => Ok(Tex { w, h, data: img.into_raw(), channels: Channels::RGB }),
+			image::DynamicImage::ImageRgba8(img) => Ok(Tex { w, h, data: img.into_raw(), channels: Channels::RGBA }),
+			image::DynamicImage::ImageLuma8(img) => Ok(Tex { w, h, data: img.into_raw(), channels: Channels::GRAYSCALE }),
+			image::DynamicImage::ImageLumaA8(img) => Ok(Tex { w, h, data: image::DynamicImage::ImageLumaA8(img).to_rgba8().into_raw(), channels: Channels::RGBA }),
+			img => Err(RendererError::UnsupportedFormat(format!("{:?}", img.color()))),
+		}
+	}
+
+	// Grows (or shrinks) the texture, keeping what was in it at the same spot
 	pub fn resize(&mut self, w: usize, h: usize) -> &mut Self {
-		let from = &self.data;
-		let mut to = vec![0 as u8; w * h * self.channels as usize];
-		for i in 0..self.h {
-			for j in 0..self.w {
-				to[i * self.w + j] = from[i * self.w + j];
-			}
+		let c = self.channels as usize;
+		let mut to = vec![0_u8; w * h * c];
+		let row = self.w.min(w) * c;
+		for y in 0..self.h.min(h) {
+			to[y * w * c..y * w * c + row].copy_from_slice(&self.data[y * self.w * c..y * self.w * c + row]);
 		}
 		self.w = w; self.h = h; self.data = to;
 		self
 	}
+
+	// Reads a pixel out as rgba, the same way GL expands the formats when sampling (grayscale goes in the red channel)
+	pub fn texel(&self, x: usize, y: usize) -> [u8; 4] {
+		let i = (y * self.w + x) * self.channels as usize;
+		let d = &self.data;
+		match self.channels {
+			Channels::RGBA => [d[i], d[i + 1], d[i + 2], d[i + 3]],
+			Channels::RGB => [d[i], d[i + 1], d[i + 2], 255],
+			Channels::GRAYSCALE => [d[i], 0, 0, 255],
+		}
+	}
+	// Whether every pixel has full alpha, which only RGBA textures can not have
+	pub fn opaque(&self) -> bool {
+		self.channels != Channels::RGBA || self.data.chunks_exact(4).all(|p| p[3] == 255)
+	}
+
+	pub fn set_texel(&mut self, x: usize, y: usize, px: [u8; 4]) {
+		let c = self.channels as usize;
+		let i = (y * self.w + x) * c;
+		match self.channels {
+			Channels::GRAYSCALE => self.data[i] = px[0],
+			_ => self.data[i..i + c].copy_from_slice(&px[..c]),
+		}
+	}
+
+	pub fn to_image(&self) -> image::DynamicImage {
+		let (w, h, data) = (self.w as u32, self.h as u32, self.data.clone());
+		match self.channels {
+			Channels::RGBA => image::DynamicImage::ImageRgba8(image::RgbaImage::from_raw(w, h, data).unwrap()),
+			Channels::RGB => image::DynamicImage::ImageRgb8(image::RgbImage::from_raw(w, h, data).unwrap()),
+			Channels::GRAYSCALE => image::DynamicImage::ImageLuma8(image::GrayImage::from_raw(w, h, data).unwrap()),
+		}
+	}
 	pub fn draw(&mut self, data: Vec<u8>, pos: Vec2<usize>, height: usize) {
+		if height == 0 { return; }
+		let width = data.len() / height;
 		for y in 0..height {
-			for x in 0..(data.len() / height) {
-				self.data[(y + pos.y) * height + (pos.x + x)] = data[y * height + x];
+			for x in 0..width {
+				self.data[(y + pos.y) * self.w + (pos.x + x)] = data[y * width + x];
 			}
 		}
 	}
 }
 
+// Where a glyph is in the atlas and how big, how far its bitmap sits from the pen (left, and bottom up from the baseline) and how far
+// the pen moves after it. Pixels at FontAtlas::SIZE
 pub struct GlyphAttributes {
-	pub pos: Vec2<u16>, pub size: Vec2<u16>, pub advance_x: u32
+	pub pos: Vec2<u16>, pub size: Vec2<u16>, pub offset: Vec2<i16>, pub advance_x: u32
 }
 
 pub struct FontAtlas {
@@ -46,9 +102,22 @@ pub struct FontAtlas {
 	lookup: HashMap<String, Box<GlyphAttributes>>,
 	places: Node,
 }
-impl<'a> FontAtlas {
+// What a FontHandle points at, the atlas plus the texture it got uploaded into
+pub struct LoadedFont {
+	pub atlas: FontAtlas,
+	pub texture: TextureHandle,
+}
+
+impl Default for FontAtlas {
+	fn default() -> Self { Self::new() }
+}
+impl FontAtlas {
 	const DEFAULTCHARS: &'static str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890[]{}()/\\=+\'\"<>,.-_?|!@#$%^&* :";
 	const STARTINGSIZE: &'static Vec2<u32> = &Vec2::<u32> { x: 128, y: 128 };
+	const MAXSIZE: u32 = 4096;
+
+	// How tall glyphs get rasterized, drawing them bigger or smaller scales from this
+	pub const SIZE: f32 = 48.0;
 	pub fn new() -> Self {
 		FontAtlas { fonts: HashMap::<String, Box<Font>>::new(),
 			lookup: HashMap::<String, Box<GlyphAttributes>>::new(),
@@ -57,30 +126,57 @@ impl<'a> FontAtlas {
 	}
 
 	// Loads all of the default chars for a font.
-	pub fn load(&mut self, name: &str, path: &str) {
-		let file: Vec<u8> = read(path).unwrap();
-		self.fonts.insert(String::from(name), Box::new(Font::from_bytes(file, FontSettings::default()).unwrap()));
+	pub fn load(&mut self, name: &str, path: &str) -> Result<()> {
+		let file: Vec<u8> = read(path)?;
+		self.fonts.insert(String::from(name), Box::new(Font::from_bytes(file, FontSettings::default()).map_err(|e| RendererError::FontParse(e.to_string()))?));
 
 		for i in FontAtlas::DEFAULTCHARS.chars() {
-			if let Err(bruh) = self.loadchar(i, name) {
-				println!("{}", bruh); return;
-			}
+			self.loadchar(i, name)?;
 		}
+		Ok(())
+	}
+	pub fn loadchar(&mut self, character: char, font: &str) -> Result<()> {
+		let (metrics, bitmap) = self.fonts.get(font).ok_or_else(|| RendererError::FontParse(format!("no font called {}", font)))?.rasterize(character, FontAtlas::SIZE);
+		let size = Vec2::<u32> { x: metrics.width as u32, y: metrics.height as u32 };
+
+		// Makes room until it fits, or the atlas gets too big to bother
+		let pos = loop {
+			if let Some(pos) = self.places.pack(&size) { break pos.pos; }
+			if self.tex.w as u32 >= FontAtlas::MAXSIZE { return Err(RendererError::AtlasFull(character)); }
+			self.grow();
+		};
+
+		// Inserts the bitmap into the texture at the specified coords
+		self.tex.draw(bitmap, Vec2::<usize> { x: pos.x as usize, y: pos.y as usize }, metrics.height);
+
+		// Inserts the character into the hashmap so we can look it up later
+		self.lookup.insert(String::from(character), Box::<GlyphAttributes>::new(GlyphAttributes {
+			size: Vec2::<u16> { x: metrics.width as u16, y: metrics.height as u16 },
+			pos: Vec2::<u16> { x: pos.x as u16, y: pos.y as u16 }, offset: Vec2::new(metrics.xmin as i16, metrics.ymin as i16),
+			advance_x: metrics.advance_width.round() as u32
+		}));
+		Ok(())
 	}
-	pub fn loadchar(&mut self, character: char, font: &str) -> Result<(), String> {
-		let (metrics, bitmap) = self.fonts[font].rasterize(character, 48.0);
-		if let Some(pos) = self.places.pack(&Vec2::<u32> { x: metrics.width as u32, y: metrics.height as u32 }) {
 
-			// Inserts the bitmap into the texture at the specified coords
-			self.tex.draw(bitmap, Vec2::<usize> { x: pos.pos.x as usize, y: pos.pos.y as usize }, pos.size.y as usize);
+	pub fn glyph(&self, character: char) -> Option<&GlyphAttributes> {
+		self.lookup.get(&String::from(character)).map(|g| &**g)
+	}
 
-			// Inserts the character into the hashmap so we can look it up later
-			self.lookup.insert(String::from(character), Box::<GlyphAttributes>::new(GlyphAttributes {
-				size: Vec2::<u16> { x: metrics.width as u16, y: metrics.height as u16 },
-				pos: Vec2::<u16> { x: pos.pos.x as u16, y: pos.pos.y as u16 }, advance_x: (metrics.advance_width / 64.0) as u32
-			}));
-      return Ok(());
-		} else { return Err(format!("Cannot insert character {} into this map because it doesn't fit.", character)); }
+	// How far above the baseline the font reaches, so text can be placed by its top
+	pub fn ascent(&self, font: &str) -> f32 {
+		self.fonts.get(font).and_then(|f| f.horizontal_line_metrics(FontAtlas::SIZE)).map_or(FontAtlas::SIZE, |m| m.ascent)
+	}
+
+	// Doubles the atlas. The old packing tree ends up in the top left corner of the new one, so every glyph already in there stays put
+	fn grow(&mut self) {
+		let (w, h) = (self.tex.w as u32, self.tex.h as u32);
+		let old = std::mem::replace(&mut self.places, Node::new(Vec2::default(), Vec2::new(w * 2, h * 2)));
+		let mut top = Node::new(Vec2::default(), Vec2::new(w * 2, h));
+		top.left = Some(Box::new(old));
+		top.right = Some(Box::new(Node::new(Vec2::new(w, 0), Vec2::new(w, h))));
+		self.places.left = Some(Box::new(top));
+		self.places.right = Some(Box::new(Node::new(Vec2::new(0, h), Vec2::new(w * 2, h))));
+		self.tex.resize(w as usize * 2, h as usize * 2);
 	}
 }
 
@@ -146,7 +242,7 @@ impl Node {
 
 		let c1 = self.left.as_mut().unwrap().pack(size);
 		if c1.is_none() { return self.right.as_mut().unwrap().pack(size); }
-		return c1;
+		c1
 	}
 }
 
@@ -172,5 +268,9 @@ fn texture_pack() {
 
 #[test]
 fn texture_atlas() {
-
+	use crate::graphics::golden;
+	let mut atlas = FontAtlas::new();
+	atlas.load("default", "./res/font.ttf").unwrap();
+	assert_eq!(atlas.lookup.len(), FontAtlas::DEFAULTCHARS.chars().count());
+	golden::check("atlas", &atlas.tex, golden::Tolerance::EXACT).unwrap();
 }
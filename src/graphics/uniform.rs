@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use glow::HasContext;
+use crate::graphics::error::{RendererError, Result};
+use crate::graphics::handle::ShaderHandle;
+
+// Something a uniform can be set to. Matrices are column major like GL wants them, and a sampler is the texture unit it reads from
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UniformValue {
+	Float(f32),
+	Vec2([f32; 2]),
+	Vec3([f32; 3]),
+	Vec4([f32; 4]),
+	Int(i32),
+	Mat3([f32; 9]),
+	Mat4([f32; 16]),
+	Sampler(i32),
+}
+
+impl From<f32> for UniformValue { fn from(v: f32) -> Self { UniformValue::Float(v) } }
+impl From<[f32; 2]> for UniformValue { fn from(v: [f32; 2]) -> Self { UniformValue::Vec2(v) } }
+impl From<[f32; 3]> for UniformValue { fn from(v: [f32; 3]) -> Self { UniformValue::Vec3(v) } }
+impl From<[f32; 4]> for UniformValue { fn from(v: [f32; 4]) -> Self { UniformValue::Vec4(v) } }
+impl From<i32> for UniformValue { fn from(v: i32) -> Self { UniformValue::Int(v) } }
+impl From<[f32; 9]> for UniformValue { fn from(v: [f32; 9]) -> Self { UniformValue::Mat3(v) } }
+impl From<[f32; 16]> for UniformValue { fn from(v: [f32; 16]) -> Self { UniformValue::Mat4(v) } }
+
+impl UniformValue {
+	// What it'd be called in GLSL, for the error messages
+	pub fn name(&self) -> &'static str {
+		match self {
+			UniformValue::Float(_) => "float",
+			UniformValue::Vec2(_) => "vec2",
+			UniformValue::Vec3(_) => "vec3",
+			UniformValue::Vec4(_) => "vec4",
+			UniformValue::Int(_) => "int",
+			UniformValue::Mat3(_) => "mat3",
+			UniformValue::Mat4(_) => "mat4",
+			UniformValue::Sampler(_) => "sampler",
+		}
+	}
+
+	// Whether it can go in a uniform GL says is this type. Bools take ints since that's how GL sets them anyway
+	pub fn fits(&self, gl_type: u32) -> bool {
+		match self {
+			UniformValue::Float(_) => gl_type == glow::FLOAT,
+			UniformValue::Vec2(_) => gl_type == glow::FLOAT_VEC2,
+			UniformValue::Vec3(_) => gl_type == glow::FLOAT_VEC3,
+			UniformValue::Vec4(_) => gl_type == glow::FLOAT_VEC4,
+			UniformValue::Int(_) => gl_type == glow::INT || gl_type == glow::BOOL,
+			UniformValue::Mat3(_) => gl_type == glow::FLOAT_MAT3,
+			UniformValue::Mat4(_) => gl_type == glow::FLOAT_MAT4,
+			UniformValue::Sampler(_) => is_sampler(gl_type),
+		}
+	}
+
+	// The same type with everything 0, what GL starts uniforms at
+	pub fn zeroed(&self) -> Self {
+		match self {
+			UniformValue::Float(_) => UniformValue::Float(0.0),
+			UniformValue::Vec2(_) => UniformValue::Vec2([0.0; 2]),
+			UniformValue::Vec3(_) => UniformValue::Vec3([0.0; 3]),
+			UniformValue::Vec4(_) => UniformValue::Vec4([0.0; 4]),
+			UniformValue::Int(_) => UniformValue::Int(0),
+			UniformValue::Mat3(_) => UniformValue::Mat3([0.0; 9]),
+			UniformValue::Mat4(_) => UniformValue::Mat4([0.0; 16]),
+			UniformValue::Sampler(_) => UniformValue::Sampler(0),
+		}
+	}
+
+	pub unsafe fn apply(&self, gl: &glow::Context, location: &glow::UniformLocation) {
+		let location = Some(location);
+		match self {
+			UniformValue::Float(v) => gl.uniform_1_f32(location, *v),
+			UniformValue::Vec2(v) => gl.uniform_2_f32_slice(location, v),
+			UniformValue::Vec3(v) => gl.uniform_3_f32_slice(location, v),
+			UniformValue::Vec4(v) => gl.uniform_4_f32_slice(location, v),
+			UniformValue::Int(v) | UniformValue::Sampler(v) => gl.uniform_1_i32(location, *v),
+			UniformValue::Mat3(v) => gl.uniform_matrix_3_f32_slice(location, false, v),
+			UniformValue::Mat4(v) => gl.uniform_matrix_4_f32_slice(location, false, v),
+		}
+	}
+}
+
+fn is_sampler(gl_type: u32) -> bool {
+	matches!(gl_type, glow::SAMPLER_1D | glow::SAMPLER_2D | glow::SAMPLER_3D | glow::SAMPLER_CUBE | glow::SAMPLER_2D_ARRAY
+		| glow::SAMPLER_2D_SHADOW | glow::INT_SAMPLER_2D | glow::UNSIGNED_INT_SAMPLER_2D | glow::SAMPLER_BUFFER)
+}
+
+// GLSL names for the types GL reports, the ones it doesn't have a UniformValue for just say what they are
+pub fn type_name(gl_type: u32) -> &'static str {
+	match gl_type {
+		glow::FLOAT => "float",
+		glow::FLOAT_VEC2 => "vec2",
+		glow::FLOAT_VEC3 => "vec3",
+		glow::FLOAT_VEC4 => "vec4",
+		glow::INT => "int",
+		glow::INT_VEC2 => "ivec2",
+		glow::INT_VEC3 => "ivec3",
+		glow::INT_VEC4 => "ivec4",
+		glow::UNSIGNED_INT => "uint",
+		glow::BOOL => "bool",
+		glow::FLOAT_MAT2 => "mat2",
+		glow::FLOAT_MAT3 => "mat3",
+		glow::FLOAT_MAT4 => "mat4",
+		t if is_sampler(t) => "sampler",
+		_ => "type with no setter",
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct UniformInfo {
+	pub location: glow::UniformLocation,
+	pub gl_type: u32,
+
+	// Elements, when it's an array
+	pub size: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeInfo {
+	pub location: u32,
+	pub gl_type: u32,
+	pub size: i32,
+}
+
+// What a linked program takes, looked up once after linking so setting things doesn't ask the driver every time
+#[derive(Debug, Clone, Default)]
+pub struct Reflection {
+	pub uniforms: HashMap<String, UniformInfo>,
+	pub attributes: HashMap<String, AttributeInfo>,
+}
+
+impl Reflection {
+	pub unsafe fn new(gl: &glow::Context, program: glow::Program) -> Self {
+		let mut ret = Reflection::default();
+
+		// Arrays come back as name[0]. The name on its own sets the first element, and each one can be set as name[i] too
+		for i in 0..gl.get_active_uniforms(program) {
+			let Some(u) = gl.get_active_uniform(program, i) else { continue; };
+			let Some(location) = gl.get_uniform_location(program, &u.name) else { continue; };
+			let name = u.name.trim_end_matches("[0]");
+			if name.len() != u.name.len() {
+				for element in 0..u.size {
+					let Some(location) = gl.get_uniform_location(program, &format!("{}[{}]", name, element)) else { continue; };
+					ret.uniforms.insert(format!("{}[{}]", name, element), UniformInfo { location, gl_type: u.utype, size: 1 });
+				}
+			}
+			ret.uniforms.insert(name.to_string(), UniformInfo { location, gl_type: u.utype, size: u.size });
+		}
+
+		// Built in ones like gl_VertexID are active too, but have no location
+		for i in 0..gl.get_active_attributes(program) {
+			let Some(a) = gl.get_active_attribute(program, i) else { continue; };
+			let Some(location) = gl.get_attrib_location(program, &a.name) else { continue; };
+			ret.attributes.insert(a.name, AttributeInfo { location, gl_type: a.atype, size: a.size });
+		}
+		ret
+	}
+
+	// The uniform to put this value in, if the program has one by that name. None means it doesn't
+	pub fn check(&self, name: &str, value: &UniformValue) -> Result<Option<&UniformInfo>> {
+		let Some(info) = self.uniforms.get(name) else { return Ok(None); };
+		if !value.fits(info.gl_type) {
+			return Err(RendererError::UniformType { name: name.to_string(), expected: type_name(info.gl_type), got: value.name() });
+		}
+		Ok(Some(info))
+	}
+}
+
+// A linked program and what's in it
+pub struct Program {
+	pub native: glow::Program,
+	pub reflection: Reflection,
+}
+
+impl Program {
+	pub unsafe fn new(gl: &glow::Context, native: glow::Program) -> Self {
+		Program { native, reflection: Reflection::new(gl, native) }
+	}
+}
+
+// A program shapes can be drawn with instead of the default one, and its own values for the uniforms in it. The program isn't
+// owned, so a few materials can share one with different values
+pub struct MaterialData {
+	pub shader: ShaderHandle,
+	pub uniforms: HashMap<String, UniformValue>,
+}
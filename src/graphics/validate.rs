@@ -0,0 +1,227 @@
+use naga::front::glsl::{Frontend, Options};
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use crate::graphics::error::{ShaderDiagnostic, ShaderStage};
+use crate::graphics::gl::{Layout, OpenGLType};
+use crate::graphics::shader::{Defines, ShaderSource, StageSource};
+
+// Type checks every stage of a shader file without needing a GPU, using naga's GLSL front end. Also makes sure the vertex inputs
+// line up with the layout vertices get uploaded with, and that the uniforms the renderer sets are declared somewhere.
+// Returns everything wrong with it, pointed at the lines of the files like the driver's errors are
+pub fn validate(src: &ShaderSource, defines: &Defines, layout: &Layout, uniforms: &[&str]) -> Vec<ShaderDiagnostic> {
+	let mut ret = Vec::new();
+	let mut declared = Vec::<String>::new();
+	let mut parsed = true;
+	for stage in src.stages() {
+
+		// naga doesn't do geometry shaders, the driver will have to catch those
+		let kind = match stage {
+			ShaderStage::Vertex => naga::ShaderStage::Vertex,
+			ShaderStage::Fragment => naga::ShaderStage::Fragment,
+			ShaderStage::Geometry => continue,
+		};
+		let Some(source) = src.stage(stage, defines) else { continue; };
+		let (source, implicit) = adapt(&source);
+
+		let module = match Frontend::default().parse(&Options::from(kind), &source.source) {
+			Ok(m) => m,
+			Err(e) => {
+				for e in e.errors {
+					let loc = e.meta.location(&source.source);
+					ret.push(src.locate(&source, loc.line_number as usize, Some(loc.line_position as usize), format!("error: {}", e.kind)));
+				}
+				parsed = false;
+				continue;
+			}
+		};
+		if let Err(e) = Validator::new(ValidationFlags::all(), Capabilities::all()).validate(&module) {
+			let loc = e.spans().next().map(|(span, _)| span.location(&source.source));
+			let mut message = format!("error: {}", e);
+			let mut cause = std::error::Error::source(&e);
+			while let Some(c) = cause { message += &format!(": {}", c); cause = c.source(); }
+			ret.push(src.locate(&source, loc.map_or(1, |l| l.line_number as usize), loc.map(|l| l.line_position as usize), message));
+		}
+
+		for (_, g) in module.global_variables.iter() {
+			if let Some(name) = &g.name { declared.push(name.clone()); }
+			if let naga::TypeInner::Struct { members, .. } = &module.types[g.ty].inner {
+				declared.extend(members.iter().filter_map(|m| m.name.clone()));
+			}
+		}
+		if stage == ShaderStage::Vertex {
+			ret.extend(check_attributes(src, &source, &module, layout, &implicit));
+		}
+	}
+
+	// Samplers (and each element of sampler arrays) got split up into a texture and a sampler on the way in. If a stage didn't parse there's no knowing what it declares
+	for name in uniforms.iter().filter(|_| parsed) {
+		if !declared.iter().any(|d| d == name || *d == format!("{}_texture", name) || *d == format!("{}_texture_0", name)) {
+			let message = format!("error: uniform `{}` isn't declared in any stage", name);
+			ret.push(ShaderDiagnostic { file: src.path.clone(), line: 1, col: None, message, source: String::new() });
+		}
+	}
+	ret
+}
+
+fn check_attributes(src: &ShaderSource, source: &StageSource, module: &naga::Module, layout: &Layout, implicit: &[String]) -> Vec<ShaderDiagnostic> {
+	let mut ret = Vec::new();
+	let Some(main) = module.entry_points.iter().find(|e| e.stage == naga::ShaderStage::Vertex) else { return ret; };
+	for arg in &main.function.arguments {
+		let (Some(name), Some(naga::Binding::Location { location, .. })) = (&arg.name, &arg.binding) else { continue; };
+
+		// Where it's declared, so the message can point at it
+		let line = source.source.lines().position(|l| {
+			let l = l.trim().trim_end_matches(';');
+			l.split_whitespace().last() == Some(name.as_str()) && l.split_whitespace().any(|t| t == "in")
+		}).map_or(1, |i| i + 1);
+		let mut flag = |message: String| ret.push(src.locate(source, line, None, message));
+
+		if implicit.contains(name) {
+			flag(format!("warning: `{}` has no layout(location = ...), so there's no telling which attribute of the layout it gets", name));
+			continue;
+		}
+		let (kind, count) = match &module.types[arg.ty].inner {
+			naga::TypeInner::Scalar(s) => (s.kind, 1),
+			naga::TypeInner::Vector { size, scalar } => (scalar.kind, *size as i32),
+			_ => continue,
+		};
+		let declared = glsl_type(kind, count);
+		match layout.attribute(*location as usize) {
+			None => flag(format!("error: `{}` is at location {} but the layout only has {} attributes", name, location, layout.len())),
+			Some((ty, supplied)) => {
+				let matches = match ty {
+					OpenGLType::Char if layout.normalized(*location as usize) => kind == naga::ScalarKind::Float,
+					OpenGLType::Float | OpenGLType::Half => kind == naga::ScalarKind::Float,
+					OpenGLType::Integer | OpenGLType::Char => matches!(kind, naga::ScalarKind::Sint | naga::ScalarKind::Uint),
+				};
+				if !matches || supplied != count {
+					let what = match ty {
+						OpenGLType::Float => "floats", OpenGLType::Half => "half floats", OpenGLType::Integer => "ints",
+						OpenGLType::Char if layout.normalized(*location as usize) => "normalized bytes", OpenGLType::Char => "bytes",
+					};
+					flag(format!("error: `{}` is declared {} but the layout supplies {} {} at location {}", name, declared, supplied, what, location));
+				}
+			}
+		}
+	}
+	ret
+}
+
+fn glsl_type(kind: naga::ScalarKind, count: i32) -> String {
+	let (scalar, prefix) = match kind {
+		naga::ScalarKind::Sint => ("int", "i"),
+		naga::ScalarKind::Uint => ("uint", "u"),
+		naga::ScalarKind::Bool => ("bool", "b"),
+		_ => ("float", ""),
+	};
+	if count == 1 { scalar.to_string() } else { format!("{}vec{}", prefix, count) }
+}
+
+// naga only takes Vulkan flavored GLSL 4.50, so the GL 3.3 stage gets rewritten line by line the way it needs: a newer #version,
+// samplers split into a texture and a sampler (with a #define so the code using them still reads the same), loose uniforms
+// wrapped in blocks, and locations given to in/outs that don't have one. Returns the in/outs that got one made up
+fn adapt(stage: &StageSource) -> (StageSource, Vec<String>) {
+	let mut ret = StageSource { stage: stage.stage, source: String::new(), origins: Vec::new() };
+	let mut implicit = Vec::new();
+	let mut arrays = Vec::<(String, String)>::new();
+	let (mut binding, mut inputs, mut outputs) = (0, 0, 0);
+	for (line, origin) in stage.source.lines().zip(&stage.origins) {
+		let mut push = |l: String| { ret.source += &l; ret.source.push('\n'); ret.origins.push(*origin); };
+		let line = index_arrays(line, &arrays);
+		let trimmed = line.trim();
+		let tokens: Vec<&str> = trimmed.trim_end_matches(';').split_whitespace().collect();
+		let io = tokens.iter().position(|t| *t == "in" || *t == "out")
+			.filter(|&i| tokens.len() == i + 3 && tokens[..i].iter().all(|t| ["flat", "smooth", "noperspective", "centroid"].contains(t)));
+		if trimmed.starts_with("#version") {
+			push("#version 450 core".into());
+		} else if trimmed.ends_with(';') && tokens.len() == 3 && tokens[0] == "uniform" {
+			let (ty, name) = (tokens[1], tokens[2]);
+			if let Some(dim) = ty.strip_prefix("sampler").or_else(|| ty.strip_prefix("isampler")).or_else(|| ty.strip_prefix("usampler")) {
+				let texture = format!("{}texture{}", &ty[..ty.len() - dim.len() - "sampler".len()], dim);
+
+				// naga has no arrays of them, so each element gets its own pair, and the uses get rewritten to match by index_arrays
+				if let Some((name, len)) = name.strip_suffix(']').and_then(|n| n.split_once('[')).and_then(|(n, l)| Some((n, l.parse::<usize>().ok()?))) {
+					let mut decl = String::new();
+					for i in 0..len {
+						decl += &format!("layout(binding = {}) uniform {} {}_texture_{}; layout(binding = {}) uniform sampler {}_sampler_{}; ", binding, texture, name, i, binding + 1, name, i);
+						binding += 2;
+					}
+					push(decl);
+					arrays.push((name.to_string(), ty.to_string()));
+				} else {
+					push(format!("layout(binding = {}) uniform {} {}_texture; layout(binding = {}) uniform sampler {}_sampler;", binding, texture, name, binding + 1, name));
+					push(format!("#define {0} {1}({0}_texture, {0}_sampler)", name, ty));
+					binding += 2;
+				}
+			} else {
+				push(format!("layout(binding = {}) uniform {}_block {{ {} {}; }};", binding, name, ty, name));
+				binding += 1;
+			}
+		} else if let Some(i) = io.filter(|_| trimmed.ends_with(';')) {
+			let location = if tokens[i] == "in" { &mut inputs } else { &mut outputs };
+			push(format!("layout(location = {}) {}", location, trimmed));
+			*location += 1;
+			implicit.push(tokens[i + 2].to_string());
+		} else {
+			push(line.to_string());
+		}
+	}
+	(ret, implicit)
+}
+
+// Turns name[3] into the pair adapt split element 3 of a sampler array into. Only constant indices can work, same as in GLSL 3.30
+fn index_arrays(line: &str, arrays: &[(String, String)]) -> String {
+	let mut line = line.to_string();
+	for (name, ty) in arrays {
+		let open = format!("{}[", name);
+		let mut from = 0;
+		while let Some(at) = line[from..].find(&open).map(|i| i + from) {
+			let start = at + open.len();
+			let Some(end) = line[start..].find(']').map(|i| i + start) else { break; };
+			let index = line[start..end].trim().to_string();
+			if index.parse::<usize>().is_err() { from = end; continue; }
+			let with = format!("{}({}_texture_{}, {}_sampler_{})", ty, name, index, name, index);
+			line.replace_range(at..=end, &with);
+			from = at + with.len();
+		}
+	}
+	line
+}
+
+#[cfg(test)]
+fn check(text: &str) -> Vec<String> {
+	use crate::graphics::gl::VertexFormat;
+	let src = ShaderSource::parse("test.glsl", text).unwrap();
+	validate(&src, &Defines::new(), &VertexFormat::default().layout(), &["u_tex"]).iter().map(|d| format!("{}:{}: {}", d.file, d.line, d.message)).collect()
+}
+
+#[test]
+fn validate_shaders() {
+	use crate::graphics::gl::{GLContext, VertexFormat};
+	let src = ShaderSource::parse("res/shaders.glsl", &std::fs::read_to_string("res/shaders.glsl").unwrap()).unwrap();
+	let mut problems = validate(&src, &Defines::new(), &VertexFormat::default().layout(), &["u_tex", "u_projection"]);
+	problems.extend(validate(&src, &crate::graphics::shader::defines(&[("INSTANCED", "1")]), &GLContext::instance_layout(), &["u_tex", "u_projection"]));
+	assert!(problems.is_empty(), "\n{}", problems.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"));
+}
+
+#[test]
+fn validate_mistakes() {
+	let shader = |vert: &str, frag: &str| format!("#version 330 core\n# vert\n{}\nout vec4 v_col;\nvoid main() {{ gl_Position = vec4(0.0); v_col = vec4(1.0); }}\n\
+		# frag\nin vec4 v_col;\nout vec4 color;\n{}\nvoid main() {{ color = v_col; }}\n", vert, frag);
+	let inputs = "layout (location = 0) in vec2 pos;\nlayout (location = 1) in vec2 txt;\nlayout (location = 2) in vec4 col;";
+	assert_eq!(check(&shader(inputs, "uniform sampler2D u_tex;")), Vec::<String>::new());
+
+	// What res/shaders.glsl used to say: GL fills in z and w, but it isn't what the layout has
+	assert_eq!(check(&shader(&inputs.replace("vec2 pos", "vec4 pos"), "uniform sampler2D u_tex;")),
+		["test.glsl:3: error: `pos` is declared vec4 but the layout supplies 2 floats at location 0"]);
+	assert_eq!(check(&shader(&inputs.replace("vec4 col", "ivec4 col").replace("location = 1", "location = 4"), "uniform sampler2D u_tex;")),
+		["test.glsl:4: error: `txt` is at location 4 but the layout only has 4 attributes",
+		"test.glsl:5: error: `col` is declared ivec4 but the layout supplies 4 normalized bytes at location 2"]);
+	assert_eq!(check(&shader(&inputs.replace("layout (location = 0) in", "in"), "uniform sampler2D u_tex;")),
+		["test.glsl:3: warning: `pos` has no layout(location = ...), so there's no telling which attribute of the layout it gets"]);
+	assert_eq!(check(&shader(inputs, "uniform vec4 u_tint;")), ["test.glsl:1: error: uniform `u_tex` isn't declared in any stage"]);
+
+	// Type errors come back against the line they're on
+	let broken = check(&shader(inputs, "uniform sampler2D u_tex;\nfloat f = nope;"));
+	assert_eq!(broken.len(), 1);
+	assert!(broken[0].starts_with("test.glsl:12: error:"), "{}", broken[0]);
+}
@@ -1,4 +1,5 @@
 #![cfg_attr(all(target_os = "windows", not(debug_assertions)), windows_subsystem = "windows")]
+#![allow(clippy::missing_safety_doc)]
 
 #[macro_use] extern crate impl_ops;
 
@@ -6,45 +7,84 @@ use glutin::event::{Event, WindowEvent};
 use glutin::event_loop::*;
 
 pub mod graphics;
-use graphics::api::GraphicsAPI;
+use graphics::api::{GraphicsAPI, Scaling, Vec2};
 use graphics::gl::GLContext;
+use graphics::term::TermContext;
 
 // main
 fn main() {
-	unsafe {
-		let event_loop: EventLoop<()> = EventLoop::new();
-		let window = glutin::ContextBuilder::new().build_windowed(
-				glutin::window::WindowBuilder::new().with_title("tetris").with_inner_size(glutin::dpi::LogicalSize::new(600., 400.)), &event_loop
-			).unwrap().make_current().unwrap();
-
-		// Sets everything up
-		let mut g: Box<dyn GraphicsAPI> = Box::<GLContext>::new(GLContext::new(&window));
-		g.setup();
-		let img = g.load_image("./res/bg.jpg").unwrap();
-
-		event_loop.run(move |event, _, control_flow| {
-			//*control_flow = ControlFlow::Wait;
-			match event {
-				Event::LoopDestroyed => { return; }
-				Event::MainEventsCleared => { window.window().request_redraw(); }
-				Event::RedrawRequested(_) => {
-					g.rect(0.0, 0.0, 0.5, 0.5);
-					g.draw();
-					window.swap_buffers().unwrap();
+	if std::env::args().any(|a| a == "--term") {
+		if let Err(e) = term() { eprintln!("{}", e); }
+		return;
+	}
+	let event_loop: EventLoop<()> = EventLoop::new();
+	let window = unsafe { glutin::ContextBuilder::new().build_windowed(
+			glutin::window::WindowBuilder::new().with_title("tetris").with_inner_size(glutin::dpi::LogicalSize::new(600., 400.)), &event_loop
+		).unwrap().make_current().unwrap() };
+
+	// Sets everything up
+	let mut g: Box<dyn GraphicsAPI> = Box::<GLContext>::new(unsafe { GLContext::new(&window) });
+	if let Err(e) = g.setup() { eprintln!("Couldn't set up the renderer: {}", e); return; }
+	let _img = g.load_image("./res/bg.jpg").map_err(|e| eprintln!("{}", e)).ok();
+
+	// The board is always 600x400, however big the window gets
+	if let Err(e) = g.virtual_resolution(600, 400, Scaling::Fit) { eprintln!("{}", e); }
+	let mut mouse = Vec2::new(0.0, 0.0);
+
+	event_loop.run(move |event, _, control_flow| {
+		//*control_flow = ControlFlow::Wait;
+		match event {
+			Event::LoopDestroyed => {}
+			Event::MainEventsCleared => { window.window().request_redraw(); }
+			Event::RedrawRequested(_) => {
+				if let Err(e) = g.rect(300.0, 100.0, 150.0, 100.0).and_then(|_| g.rect(mouse.x - 4.0, mouse.y - 4.0, 8.0, 8.0)).and_then(|_| g.draw()) {
+					eprintln!("{}", e);
+					*control_flow = ControlFlow::Exit;
 				}
-				Event::WindowEvent { ref event, .. } => match event {
-					WindowEvent::Resized(physical_size) => {
-						window.resize(*physical_size);
-					}
-					WindowEvent::CloseRequested => {
-						g.destroy();
-						*control_flow = ControlFlow::Exit
-					}
-					_ => (),
-				},
-				_ => (),
+				window.swap_buffers().unwrap();
 			}
-		});
-	}
+			Event::WindowEvent { ref event, .. } => match event {
+				WindowEvent::Resized(physical_size) => {
+					window.resize(*physical_size);
+					let scale = window.window().scale_factor() as f32;
+					if let Err(e) = g.resize(physical_size.width, physical_size.height, scale) { eprintln!("{}", e); }
+				}
+				WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } => {
+					window.resize(**new_inner_size);
+					if let Err(e) = g.resize(new_inner_size.width, new_inner_size.height, *scale_factor as f32) { eprintln!("{}", e); }
+				}
+				WindowEvent::CursorMoved { position, .. } => {
+					mouse = g.screen().to_logical(Vec2::new(position.x as f32, position.y as f32));
+				}
+				WindowEvent::CloseRequested => {
+					if let Err(e) = g.destroy() { eprintln!("{}", e); }
+					*control_flow = ControlFlow::Exit
+				}
+				_ => (),
+			},
+			_ => (),
+		}
+	});
 }
 
+
+// Same thing but drawn in the terminal, for playing over ssh. q or escape quits
+fn term() -> graphics::error::Result<()> {
+	use crossterm::event::KeyCode;
+	let mut g = TermContext::terminal();
+	g.setup()?;
+
+	// Gives the terminal back even if a frame fails
+	let ret = (|| {
+		let _img = g.load_image("./res/bg.jpg")?;
+		loop {
+			if g.keys().iter().any(|k| matches!(k, KeyCode::Char('q') | KeyCode::Esc)) { return Ok(()); }
+			let size = g.soft.screen.logical_size();
+			g.rect(size.x / 2.0, size.y / 4.0, size.x / 4.0, size.y / 4.0)?;
+			g.draw()?;
+			std::thread::sleep(std::time::Duration::from_millis(1000 / 30));
+		}
+	})();
+	g.destroy()?;
+	ret
+}
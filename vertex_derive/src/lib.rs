@@ -0,0 +1,107 @@
+// #[derive(VertexLayout)] for the renderer's vertex structs, so the Layout handed to GL comes from the struct itself instead of being
+// kept in sync with it by hand. Fields become attributes at locations 0.. in order, at the offsets the compiler actually gave them
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Expr, Fields, Lit, Type};
+
+#[proc_macro_derive(VertexLayout, attributes(normalized))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	match expand(&input) {
+		Ok(ret) => ret.into(),
+		Err(e) => e.to_compile_error().into(),
+	}
+}
+
+// What GL gets told a field is
+enum Kind { Float, Integer, Char, Half }
+
+fn expand(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+	let name = &input.ident;
+
+	// Without repr(C) the compiler can reorder fields, and then the offsets are whatever it felt like
+	let repr_c = input.attrs.iter().any(|a| a.path().is_ident("repr") && a.parse_args::<syn::Ident>().is_ok_and(|i| i == "C"));
+	if !repr_c { return Err(Error::new(Span::call_site(), "vertex structs need #[repr(C)] so the fields stay where the layout says")); }
+	let Data::Struct(data) = &input.data else { return Err(Error::new(Span::call_site(), "only structs can be vertices")); };
+	let Fields::Named(fields) = &data.fields else { return Err(Error::new(Span::call_site(), "vertex fields need names")); };
+
+	let mut attributes = Vec::new();
+	let mut sizes = Vec::new();
+	for field in fields.named.iter() {
+		let ident = field.ident.as_ref().unwrap();
+		let normalized = field.attrs.iter().any(|a| a.path().is_ident("normalized"));
+		let (kind, count) = attribute(&field.ty)?;
+		if normalized && !matches!(kind, Kind::Char) {
+			return Err(Error::new_spanned(field, "only u8 fields can be #[normalized], everything else goes to the shader as it is"));
+		}
+		let kind = match kind {
+			Kind::Float => quote!(crate::graphics::gl::OpenGLType::Float),
+			Kind::Integer => quote!(crate::graphics::gl::OpenGLType::Integer),
+			Kind::Char => quote!(crate::graphics::gl::OpenGLType::Char),
+			Kind::Half => quote!(crate::graphics::gl::OpenGLType::Half),
+		};
+		attributes.push(quote! { ret.add(#kind, #count, ::core::mem::offset_of!(#name, #ident) as i32, #normalized); });
+		let ty = &field.ty;
+		sizes.push(quote! { ::core::mem::size_of::<#ty>() });
+	}
+
+	let message = format!("{} has padding between or after its fields, which would get uploaded as garbage", name);
+	Ok(quote! {
+		impl crate::graphics::gl::VertexLayout for #name {
+			fn layout() -> crate::graphics::gl::Layout {
+				let mut ret = crate::graphics::gl::Layout::new();
+				#(#attributes)*
+				ret.stride = ::core::mem::size_of::<#name>() as i32;
+				ret
+			}
+		}
+
+		// Checked when it's compiled rather than when a vertex comes out wrong
+		const _: () = assert!(::core::mem::size_of::<#name>() == 0 #(+ #sizes)*, #message);
+	})
+}
+
+// The attribute a field type turns into, and how many components it has
+fn attribute(ty: &Type) -> Result<(Kind, i32), Error> {
+	let unsupported = || Error::new_spanned(ty, "vertex fields can be f32, i32, f16, Vec2<f32>, or arrays of them (or of u8, which are ints to the shader unless they're #[normalized])");
+	match ty {
+		Type::Array(array) => {
+			let Expr::Lit(syn::ExprLit { lit: Lit::Int(len), .. }) = &array.len else { return Err(Error::new_spanned(&array.len, "array lengths have to be a number")); };
+			let count = len.base10_parse::<i32>()?;
+			if !(1..=4).contains(&count) { return Err(Error::new_spanned(&array.len, "attributes have 1 to 4 components")); }
+			match scalar(&array.elem) {
+				Some("u8") => Ok((Kind::Char, count)),
+				Some(s) => Ok((kind(s).ok_or_else(unsupported)?, count)),
+				None => Err(unsupported()),
+			}
+		}
+		Type::Path(path) => {
+			let last = path.path.segments.last().ok_or_else(unsupported)?;
+			if last.ident == "Vec2" {
+				let syn::PathArguments::AngleBracketed(args) = &last.arguments else { return Err(unsupported()); };
+				return match args.args.first() {
+					Some(syn::GenericArgument::Type(t)) if scalar(t) == Some("f32") => Ok((Kind::Float, 2)),
+					_ => Err(unsupported()),
+				};
+			}
+			Ok((scalar(ty).and_then(kind).ok_or_else(unsupported)?, 1))
+		}
+		_ => Err(unsupported()),
+	}
+}
+
+fn scalar(ty: &Type) -> Option<&'static str> {
+	let Type::Path(path) = ty else { return None; };
+	let last = path.path.segments.last()?;
+	["f32", "i32", "u8", "f16"].into_iter().find(|s| last.ident == s)
+}
+
+fn kind(scalar: &str) -> Option<Kind> {
+	match scalar {
+		"f32" => Some(Kind::Float),
+		"i32" => Some(Kind::Integer),
+		"f16" => Some(Kind::Half),
+		_ => None,
+	}
+}
@@ -0,0 +1,5 @@
+// What the derive turns down, and the errors it points at while doing it
+#[test]
+fn compile_fail() {
+	trybuild::TestCases::new().compile_fail("tests/fail/*.rs");
+}
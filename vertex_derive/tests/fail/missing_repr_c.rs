@@ -0,0 +1,10 @@
+use vertex_derive::VertexLayout;
+
+#[derive(VertexLayout)]
+struct Vertex {
+	pos: [f32; 2],
+	#[normalized]
+	col: [u8; 4],
+}
+
+fn main() {}
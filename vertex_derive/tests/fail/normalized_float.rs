@@ -0,0 +1,11 @@
+use vertex_derive::VertexLayout;
+
+#[derive(VertexLayout)]
+#[repr(C)]
+struct Vertex {
+	pos: [f32; 2],
+	#[normalized]
+	col: [f32; 4],
+}
+
+fn main() {}